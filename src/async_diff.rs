@@ -0,0 +1,462 @@
+//! Contains tokio-compatible diff application and creation
+//!
+//! Available with `async` feature. `apply_diff_async` and `create_diff_async` return futures
+//! which read and write through `AsyncRead` and `AsyncWrite`, so patches can be streamed
+//! without blocking the executor threads. Creation hashes the inputs on the blocking pool.
+
+use decoder::{Decoder, Event};
+use diff::create_diff;
+use diff_block::DiffBlockN;
+use indexes::WithIndexes;
+use std::cmp::min;
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result as IOResult, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::{spawn_blocking, JoinHandle};
+
+const BUFFER_SIZE: usize = 1024 * 64;
+
+/// Number of chunks `create_diff_async` produces ahead of the output
+const CHANNEL_SIZE: usize = 4;
+
+/// Reads into `buf`, returns number of read bytes
+fn poll_read<T: AsyncRead + Unpin>(
+	input: &mut T,
+	cx: &mut Context,
+	buf: &mut [u8],
+) -> Poll<IOResult<usize>> {
+	let mut buf = ReadBuf::new(buf);
+	match Pin::new(input).poll_read(cx, &mut buf) {
+		Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+		Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+		Poll::Pending => Poll::Pending,
+	}
+}
+
+/// Bytes waiting to be written to the output
+struct Pending {
+	buf: Vec<u8>,
+	start: usize,
+	end: usize,
+}
+
+impl Pending {
+	fn new() -> Self {
+		Self {
+			buf: vec![0; BUFFER_SIZE],
+			start: 0,
+			end: 0,
+		}
+	}
+
+	/// Writes all pending bytes
+	fn poll_write<W: AsyncWrite + Unpin>(
+		&mut self,
+		output: &mut W,
+		cx: &mut Context,
+	) -> Poll<IOResult<()>> {
+		while self.start < self.end {
+			match Pin::new(&mut *output).poll_write(cx, &self.buf[self.start..self.end]) {
+				Poll::Ready(Ok(0)) => {
+					return Poll::Ready(Err(Error::new(
+						ErrorKind::WriteZero,
+						"Failed to write whole buffer",
+					)))
+				}
+				Poll::Ready(Ok(x)) => self.start += x,
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+				Poll::Pending => return Poll::Pending,
+			}
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	/// Sets pending bytes to the first `size` bytes of the buffer
+	fn fill(&mut self, size: usize) {
+		self.start = 0;
+		self.end = size;
+	}
+
+	/// Replaces the buffer with `buf`, all of its bytes are pending
+	fn set(&mut self, buf: Vec<u8>) {
+		self.end = buf.len();
+		self.start = 0;
+		self.buf = buf;
+	}
+}
+
+enum ApplyState {
+	Diff,
+	CopySource(u64),
+	Remove(u64),
+	Flush,
+	Done,
+}
+
+/// Future of `apply_diff_async`
+pub struct ApplyDiffAsync<'a, T: AsyncRead + Unpin, U: AsyncRead + Unpin, W: AsyncWrite + Unpin> {
+	file: &'a mut T,
+	diff: &'a mut U,
+	output: &'a mut W,
+	decoder: Decoder,
+	pending: Pending,
+	state: ApplyState,
+}
+
+/// Same as `apply_diff`, but reads and writes asynchronously
+///
+/// Fails with `ErrorKind::UnexpectedEof` if the diff ends in the middle of the block
+pub fn apply_diff_async<'a, T, U, W>(
+	file: &'a mut T,
+	diff: &'a mut U,
+	output: &'a mut W,
+) -> ApplyDiffAsync<'a, T, U, W>
+where
+	T: AsyncRead + Unpin,
+	U: AsyncRead + Unpin,
+	W: AsyncWrite + Unpin,
+{
+	ApplyDiffAsync {
+		file,
+		diff,
+		output,
+		decoder: Decoder::new(),
+		pending: Pending::new(),
+		state: ApplyState::Diff,
+	}
+}
+
+impl<'a, T, U, W> Future for ApplyDiffAsync<'a, T, U, W>
+where
+	T: AsyncRead + Unpin,
+	U: AsyncRead + Unpin,
+	W: AsyncWrite + Unpin,
+{
+	type Output = IOResult<()>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		loop {
+			match this.pending.poll_write(this.output, cx) {
+				Poll::Ready(Ok(())) => {}
+				x => return x,
+			}
+			let (input, left): (&mut dyn AsyncReadUnpin, u64) = match this.state {
+				ApplyState::Diff => (this.diff, this.decoder.needed()),
+				ApplyState::CopySource(0) | ApplyState::Remove(0) => {
+					this.state = ApplyState::Diff;
+					continue;
+				}
+				ApplyState::CopySource(left) | ApplyState::Remove(left) => (this.file, left),
+				ApplyState::Flush => {
+					match Pin::new(&mut *this.output).poll_flush(cx) {
+						Poll::Ready(Ok(())) => this.state = ApplyState::Done,
+						x => return x,
+					}
+					continue;
+				}
+				ApplyState::Done => return Poll::Ready(Ok(())),
+			};
+			let size = min(left, BUFFER_SIZE as u64) as usize;
+			let read = match input.poll_read_unpin(cx, &mut this.pending.buf[..size]) {
+				Poll::Ready(Ok(x)) => x,
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+				Poll::Pending => return Poll::Pending,
+			};
+			this.state = match this.state {
+				ApplyState::CopySource(_) if read == 0 => ApplyState::Diff,
+				ApplyState::Diff if read == 0 && !this.decoder.is_complete() => {
+					return Poll::Ready(Err(Error::new(
+						ErrorKind::UnexpectedEof,
+						"Diff ends in the middle of the block",
+					)))
+				}
+				_ if read == 0 => ApplyState::Flush,
+				ApplyState::CopySource(left) => {
+					this.pending.fill(read);
+					ApplyState::CopySource(left - read as u64)
+				}
+				ApplyState::Remove(left) => ApplyState::Remove(left - read as u64),
+				_ => match this.decoder.feed(&this.pending.buf[..read])?.1 {
+					Some(Event::Block(DiffBlockN::Skip(size))) => {
+						ApplyState::CopySource(u64::from(size))
+					}
+					Some(Event::Block(DiffBlockN::Remove(size)))
+					| Some(Event::Block(DiffBlockN::Replace(size, _)))
+					| Some(Event::Block(DiffBlockN::ReplaceWithSameLength(size))) => {
+						ApplyState::Remove(u64::from(size))
+					}
+					Some(Event::Data(_)) => {
+						this.pending.fill(read);
+						ApplyState::Diff
+					}
+					_ => ApplyState::Diff,
+				},
+			};
+		}
+	}
+}
+
+/// Object safe `AsyncRead` for unpinned readers
+trait AsyncReadUnpin {
+	fn poll_read_unpin(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<IOResult<usize>>;
+}
+
+impl<T: AsyncRead + Unpin> AsyncReadUnpin for T {
+	fn poll_read_unpin(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<IOResult<usize>> {
+		poll_read(self, cx, buf)
+	}
+}
+
+/// Output of the blocking diff creation, sends written chunks to `CreateDiffAsync`
+struct ChannelWriter(Sender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+	fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+		self.0
+			.blocking_send(buf.to_vec())
+			.map_err(|_| Error::new(ErrorKind::BrokenPipe, "Diff future is dropped"))?;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> IOResult<()> {
+		Ok(())
+	}
+}
+
+/// Future of `create_diff_async`
+pub struct CreateDiffAsync<'a, W: AsyncWrite + Unpin> {
+	chunks: Receiver<Vec<u8>>,
+	task: JoinHandle<IOResult<()>>,
+	output: &'a mut W,
+	pending: Pending,
+	done: bool,
+}
+
+/// Same as `create_diff`, but writes diff asynchronously
+///
+/// Inputs are hashed and read by `create_diff` on the tokio blocking pool, the future only
+/// writes produced chunks to `output`. Must be called within a tokio runtime. Dropping the future
+/// stops the blocking task at the next written chunk
+pub fn create_diff_async<'a, T, U, W>(
+	mut original: T,
+	mut edited: U,
+	output: &'a mut W,
+) -> CreateDiffAsync<'a, W>
+where
+	T: WithIndexes + Send + 'static,
+	U: WithIndexes + Send + 'static,
+	W: AsyncWrite + Unpin,
+{
+	let (sender, chunks) = channel(CHANNEL_SIZE);
+	let mut writer = ChannelWriter(sender);
+	let task = spawn_blocking(move || create_diff(&mut original, &mut edited, &mut writer));
+	CreateDiffAsync {
+		chunks,
+		task,
+		output,
+		pending: Pending::new(),
+		done: false,
+	}
+}
+
+impl<'a, W: AsyncWrite + Unpin> Future for CreateDiffAsync<'a, W> {
+	type Output = IOResult<()>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		loop {
+			match this.pending.poll_write(this.output, cx) {
+				Poll::Ready(Ok(())) => {}
+				x => return x,
+			}
+			if this.done {
+				return Pin::new(&mut *this.output).poll_flush(cx);
+			}
+			match this.chunks.poll_recv(cx) {
+				Poll::Ready(Some(chunk)) => this.pending.set(chunk),
+				Poll::Ready(None) => match Pin::new(&mut this.task).poll(cx) {
+					Poll::Ready(Ok(result)) => {
+						result?;
+						this.done = true;
+					}
+					Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::other(e))),
+					Poll::Pending => return Poll::Pending,
+				},
+				Poll::Pending => return Poll::Pending,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod async_diff_tests {
+	use super::{apply_diff_async, create_diff_async};
+	use diff::{apply_diff, create_diff};
+	use std::fs::read;
+	use std::io::{Cursor, ErrorKind, Result as IOResult};
+	use std::pin::Pin;
+	use std::task::{Context, Poll};
+	use test_mod::TextFile;
+	use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+	use tokio::runtime::Builder;
+
+	/// Returns `Pending` before every byte read or written
+	struct Chunked<T> {
+		inner: T,
+		ready: bool,
+	}
+
+	impl<T> Chunked<T> {
+		fn new(inner: T) -> Self {
+			Self {
+				inner,
+				ready: false,
+			}
+		}
+
+		fn wait(&mut self, cx: &mut Context) -> bool {
+			self.ready = !self.ready;
+			if !self.ready {
+				return true;
+			}
+			cx.waker().wake_by_ref();
+			false
+		}
+	}
+
+	impl<T: AsyncRead + Unpin> AsyncRead for Chunked<T> {
+		fn poll_read(
+			self: Pin<&mut Self>,
+			cx: &mut Context,
+			buf: &mut ReadBuf,
+		) -> Poll<IOResult<()>> {
+			let this = self.get_mut();
+			if !this.wait(cx) {
+				return Poll::Pending;
+			}
+			let mut byte = [0u8; 1];
+			let mut one = ReadBuf::new(&mut byte);
+			match Pin::new(&mut this.inner).poll_read(cx, &mut one) {
+				Poll::Ready(Ok(())) => {
+					buf.put_slice(one.filled());
+					Poll::Ready(Ok(()))
+				}
+				x => x,
+			}
+		}
+	}
+
+	impl<T: AsyncWrite + Unpin> AsyncWrite for Chunked<T> {
+		fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<IOResult<usize>> {
+			let this = self.get_mut();
+			if !this.wait(cx) {
+				return Poll::Pending;
+			}
+			Pin::new(&mut this.inner).poll_write(cx, &buf[..1])
+		}
+
+		fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<IOResult<()>> {
+			Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+		}
+
+		fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<IOResult<()>> {
+			Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+		}
+	}
+
+	fn diff(original: &str, edited: &str) -> Vec<u8> {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path(original),
+			&mut TextFile::from_path(edited),
+			&mut diff,
+		)
+		.unwrap();
+		diff
+	}
+
+	#[test]
+	fn works_test() {
+		let runtime = Builder::new_current_thread().build().unwrap();
+		let _runtime = runtime.enter();
+		let pairs = [
+			["./test_data/a_a.txt", "./test_data/a_b.txt"],
+			["./test_data/a_c.txt", "./test_data/a_d.txt"],
+		];
+		for pair in pairs.iter() {
+			let expected = diff(pair[0], pair[1]);
+			let mut diff = vec![];
+			runtime
+				.block_on(create_diff_async(
+					TextFile::from_path(pair[0]),
+					TextFile::from_path(pair[1]),
+					&mut diff,
+				))
+				.unwrap();
+			assert_eq!(diff, expected);
+
+			let mut target = vec![];
+			runtime
+				.block_on(apply_diff_async(
+					&mut Cursor::new(read(pair[0]).unwrap()),
+					&mut Cursor::new(&diff),
+					&mut target,
+				))
+				.unwrap();
+			let mut expected = vec![];
+			apply_diff(
+				&mut Cursor::new(read(pair[0]).unwrap()),
+				&mut Cursor::new(&diff),
+				&mut expected,
+			)
+			.unwrap();
+			assert_eq!(target, expected);
+			assert_eq!(target, read(pair[1]).unwrap());
+		}
+	}
+
+	#[test]
+	fn pending_test() {
+		let runtime = Builder::new_current_thread().build().unwrap();
+		let _runtime = runtime.enter();
+		let expected = diff("./test_data/a_a.txt", "./test_data/a_b.txt");
+
+		let mut diff = Chunked::new(vec![]);
+		runtime
+			.block_on(create_diff_async(
+				TextFile::from_path("./test_data/a_a.txt"),
+				TextFile::from_path("./test_data/a_b.txt"),
+				&mut diff,
+			))
+			.unwrap();
+		assert_eq!(diff.inner, expected);
+
+		let mut target = Chunked::new(vec![]);
+		runtime
+			.block_on(apply_diff_async(
+				&mut Chunked::new(Cursor::new(read("./test_data/a_a.txt").unwrap())),
+				&mut Chunked::new(Cursor::new(&expected)),
+				&mut target,
+			))
+			.unwrap();
+		assert_eq!(target.inner, read("./test_data/a_b.txt").unwrap());
+	}
+
+	#[test]
+	fn truncated_test() {
+		let runtime = Builder::new_current_thread().build().unwrap();
+		let diff = diff("./test_data/a_a.txt", "./test_data/a_b.txt");
+		for size in [1, 4, diff.len() - 1].iter() {
+			let result = runtime.block_on(apply_diff_async(
+				&mut Chunked::new(Cursor::new(read("./test_data/a_a.txt").unwrap())),
+				&mut Chunked::new(Cursor::new(&diff[..*size])),
+				&mut vec![],
+			));
+			assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+		}
+	}
+}
@@ -0,0 +1,297 @@
+//! Contains crash-safe diff application to filesystem paths
+//!
+//! Target is written to a temporary file next to it, synced to disk, verified and only then
+//! renamed over the target path. Interrupted application leaves either the old target or
+//! the new one, never a truncated file. Temporary file is removed on errors.
+//!
+//! Diffs are applied with `apply_diff_strict`, so truncated diffs never replace the target.
+
+use counting::HashingWriter;
+use hasher::{BlockHasher, Digest, HasherState, Xxh3Hasher};
+use std::fs::{remove_file, rename, File, OpenOptions};
+use std::io::{BufReader, Error, ErrorKind, Read, Result as IOResult};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use strict::apply_diff_strict;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary file which is removed on drop unless persisted
+struct TempFile {
+	path: PathBuf,
+	file: Option<File>,
+}
+
+impl TempFile {
+	/// Creates new file in the directory of `target`
+	fn create(target: &Path) -> IOResult<Self> {
+		let name = target
+			.file_name()
+			.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Target path has no file name"))?;
+		let dir = target.parent().unwrap_or_else(|| Path::new(""));
+		loop {
+			let path = dir.join(format!(
+				".{}.{}.{}.tmp",
+				name.to_string_lossy(),
+				process::id(),
+				TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+			));
+			match OpenOptions::new().write(true).create_new(true).open(&path) {
+				Ok(file) => {
+					return Ok(Self {
+						path,
+						file: Some(file),
+					})
+				}
+				Err(ref e) if e.kind() == ErrorKind::AlreadyExists => continue,
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
+	/// Renames file to `target`
+	fn persist(mut self, target: &Path) -> IOResult<()> {
+		self.file.take();
+		rename(&self.path, target)?;
+		self.path = PathBuf::new();
+		Ok(())
+	}
+}
+
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		self.file.take();
+		if self.path != PathBuf::new() {
+			let _ = remove_file(&self.path);
+		}
+	}
+}
+
+/// Applies diff to the file at `source_path` and atomically replaces `target_path` with the result
+///
+/// Written file is read back and compared with the produced data before it replaces the target,
+/// which keeps permissions of the replaced file. `source_path` and `target_path` may be the same.
+/// Returns size of the target.
+///
+/// Failure to sync the directory is reported after the target is already replaced,
+/// its error message says so
+pub fn apply_diff_to_path<P: AsRef<Path>, Q: AsRef<Path>, U: Read>(
+	source_path: P,
+	diff: &mut U,
+	target_path: Q,
+) -> IOResult<u64> {
+	apply_with_check(
+		source_path.as_ref(),
+		diff,
+		target_path.as_ref(),
+		&Xxh3Hasher,
+		None,
+	)
+}
+
+/// Same as `apply_diff_to_path`, but also checks that the target digest equals `expected`
+///
+/// Target is left untouched if it doesn't
+pub fn apply_diff_to_path_with_hash<P: AsRef<Path>, Q: AsRef<Path>, U: Read, H: BlockHasher>(
+	source_path: P,
+	diff: &mut U,
+	target_path: Q,
+	hasher: H,
+	expected: &Digest,
+) -> IOResult<u64> {
+	apply_with_check(
+		source_path.as_ref(),
+		diff,
+		target_path.as_ref(),
+		&hasher,
+		Some(expected),
+	)
+}
+
+fn apply_with_check<U: Read, H: BlockHasher>(
+	source_path: &Path,
+	diff: &mut U,
+	target_path: &Path,
+	hasher: &H,
+	expected: Option<&Digest>,
+) -> IOResult<u64> {
+	let mut temp = TempFile::create(target_path)?;
+	let (written, digest) = {
+		let mut source = BufReader::new(File::open(source_path)?);
+		let mut output = HashingWriter {
+			output: temp.file.as_mut().expect("Temporary file is open"),
+			state: hasher.start(),
+			written: 0,
+		};
+		apply_diff_strict(&mut source, diff, &mut output, false)?;
+		(output.written, output.state.finish())
+	};
+	let file = temp.file.as_mut().expect("Temporary file is open");
+	if let Ok(metadata) = target_path.metadata() {
+		file.set_permissions(metadata.permissions())?;
+	}
+	file.sync_all()?;
+
+	let size = temp.path.metadata()?.len();
+	if size != written {
+		return Err(Error::new(
+			ErrorKind::InvalidData,
+			format!("Written file has {} bytes, expected {}", size, written),
+		));
+	}
+	let stored = hasher.hash(&mut BufReader::new(File::open(&temp.path)?))?;
+	if stored != digest {
+		return Err(Error::new(
+			ErrorKind::InvalidData,
+			"Written file doesn't match produced data",
+		));
+	}
+	if let Some(expected) = expected {
+		if digest != *expected {
+			return Err(Error::new(
+				ErrorKind::InvalidData,
+				format!(
+					"Target digest {} doesn't match expected {}",
+					digest.to_hex(),
+					expected.to_hex()
+				),
+			));
+		}
+	}
+
+	temp.persist(target_path)?;
+	sync_dir(target_path).map_err(|e| {
+		Error::new(
+			e.kind(),
+			format!("Target is replaced, but its directory is not synced: {}", e),
+		)
+	})?;
+	Ok(written)
+}
+
+/// Makes rename durable by syncing the directory of `path`
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> IOResult<()> {
+	match path.parent() {
+		Some(dir) if dir != Path::new("") => File::open(dir)?.sync_all(),
+		_ => File::open(".")?.sync_all(),
+	}
+}
+
+/// Directories can't be opened on other platforms, rename is already durable on Windows
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> IOResult<()> {
+	Ok(())
+}
+
+#[cfg(test)]
+mod atomic_apply_tests {
+	use super::{apply_diff_to_path, apply_diff_to_path_with_hash};
+	use diff::create_diff;
+	use hasher::{BlockHasher, Digest, Sha256Hasher};
+	use std::env::temp_dir;
+	use std::fs::{create_dir_all, read, read_dir, remove_dir_all, write};
+	use std::io::{Cursor, ErrorKind};
+	use std::process;
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let dir = temp_dir().join(format!("bin_diff_atomic_{}", process::id()));
+		create_dir_all(&dir).unwrap();
+		let target = dir.join("target.txt");
+		write(&target, b"old contents").unwrap();
+
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		let expected = read("./test_data/a_b.txt").unwrap();
+
+		let wrong = Digest::new(&[0; 32]);
+		assert!(apply_diff_to_path_with_hash(
+			"./test_data/a_a.txt",
+			&mut Cursor::new(&diff),
+			&target,
+			Sha256Hasher,
+			&wrong,
+		)
+		.is_err());
+		assert_eq!(read(&target).unwrap(), b"old contents");
+		assert!(
+			apply_diff_to_path("./test_data/missing.txt", &mut Cursor::new(&diff), &target)
+				.is_err()
+		);
+		assert_eq!(read_dir(&dir).unwrap().count(), 1);
+
+		let size =
+			apply_diff_to_path("./test_data/a_a.txt", &mut Cursor::new(&diff), &target).unwrap();
+		assert_eq!(size, expected.len() as u64);
+		assert_eq!(read(&target).unwrap(), expected);
+
+		let digest = Sha256Hasher.hash(&mut Cursor::new(&expected)).unwrap();
+		let copy = dir.join("copy.txt");
+		write(&copy, read("./test_data/a_a.txt").unwrap()).unwrap();
+		apply_diff_to_path_with_hash(&copy, &mut Cursor::new(&diff), &copy, Sha256Hasher, &digest)
+			.unwrap();
+		assert_eq!(read(&copy).unwrap(), expected);
+		assert_eq!(read_dir(&dir).unwrap().count(), 2);
+
+		remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn truncated_test() {
+		let dir = temp_dir().join(format!("bin_diff_atomic_truncated_{}", process::id()));
+		create_dir_all(&dir).unwrap();
+		let target = dir.join("target.txt");
+		write(&target, b"old contents").unwrap();
+
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		let truncated = &diff[..diff.len() - 1];
+		let error = apply_diff_to_path("./test_data/a_a.txt", &mut Cursor::new(truncated), &target)
+			.unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+		assert_eq!(read(&target).unwrap(), b"old contents");
+		assert_eq!(read_dir(&dir).unwrap().count(), 1);
+
+		remove_dir_all(&dir).unwrap();
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn permissions_test() {
+		use std::fs::{metadata, set_permissions, Permissions};
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = temp_dir().join(format!("bin_diff_atomic_permissions_{}", process::id()));
+		create_dir_all(&dir).unwrap();
+		let target = dir.join("target.txt");
+		write(&target, b"old contents").unwrap();
+		set_permissions(&target, Permissions::from_mode(0o640)).unwrap();
+
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		apply_diff_to_path("./test_data/a_a.txt", &mut Cursor::new(&diff), &target).unwrap();
+		assert_eq!(read(&target).unwrap(), read("./test_data/a_b.txt").unwrap());
+		let mode = metadata(&target).unwrap().permissions().mode();
+		assert_eq!(mode & 0o777, 0o640);
+
+		remove_dir_all(&dir).unwrap();
+	}
+}
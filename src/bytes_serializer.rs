@@ -3,13 +3,13 @@ use std::io::{Read, Result};
 pub struct BytesSerializer<T> {
 	pos: usize,
 	value: T,
-	closure: Box<dyn FnMut(&mut usize, &mut T, &mut [u8]) -> Result<usize>>,
+	closure: Box<dyn FnMut(&mut usize, &mut T, &mut [u8]) -> Result<usize> + Send>,
 }
 
 impl<T> BytesSerializer<T> {
 	pub fn new(
 		value: T,
-		closure: Box<dyn FnMut(&mut usize, &mut T, &mut [u8]) -> Result<usize>>,
+		closure: Box<dyn FnMut(&mut usize, &mut T, &mut [u8]) -> Result<usize> + Send>,
 	) -> Self {
 		Self {
 			pos: 0,
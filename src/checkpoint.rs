@@ -0,0 +1,328 @@
+//! Contains resumable diff application
+//!
+//! Long running `apply_diff` can record `Checkpoint`s between blocks. After a crash
+//! application is resumed from the last checkpoint instead of starting over.
+//!
+//! Output is hashed in segments between checkpoints, each segment hash starts from the digest
+//! of the previous one. Resuming reads back only the last segment to verify it, so it costs
+//! about `interval` bytes of reading regardless of the output size.
+
+use counting::{CountingReader, HashingWriter};
+use diff::apply_next_block;
+use functions::{u64_to_u8_be_vec, vec_to_u64_be};
+use hasher::{BlockHasher, Digest, HasherState, Xxh3Hasher, Xxh3State, MAX_DIGEST_LENGTH};
+use std::cmp::min;
+use std::io::{BufWriter, Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom, Write};
+
+/// Progress of the diff application
+///
+/// Offsets are absolute positions in the source, diff and output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+	/// Offset of the next diff block
+	pub diff_offset: u64,
+	/// Offset of the next source byte
+	pub source_offset: u64,
+	/// Number of bytes written to the output
+	pub written: u64,
+	/// Output offset of the previous checkpoint, where the last hashed segment starts
+	pub segment_start: u64,
+	/// Digest of the output up to `segment_start`
+	pub previous: Digest,
+	/// Digest of the output up to `written`
+	pub digest: Digest,
+}
+
+impl Checkpoint {
+	/// Returns checkpoint of the application which hasn't started yet
+	pub fn new() -> Self {
+		Self {
+			diff_offset: 0,
+			source_offset: 0,
+			written: 0,
+			segment_start: 0,
+			previous: Digest::new(&[]),
+			digest: segment_state(&Digest::new(&[])).finish(),
+		}
+	}
+
+	/// Serializes checkpoint to binary representation
+	///
+	/// ```bash
+	/// diff_offset : 8
+	/// source_offset : 8
+	/// written : 8
+	/// segment_start : 8
+	/// previous_length : 1
+	/// previous : previous_length
+	/// digest_length : 1
+	/// digest : digest_length
+	/// ```
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out = vec![];
+		out.extend_from_slice(&u64_to_u8_be_vec(self.diff_offset));
+		out.extend_from_slice(&u64_to_u8_be_vec(self.source_offset));
+		out.extend_from_slice(&u64_to_u8_be_vec(self.written));
+		out.extend_from_slice(&u64_to_u8_be_vec(self.segment_start));
+		for digest in [&self.previous, &self.digest].iter() {
+			out.push(digest.as_bytes().len() as u8);
+			out.extend_from_slice(digest.as_bytes());
+		}
+		out
+	}
+
+	/// Parses checkpoint from binary representation produced by `to_bytes`
+	pub fn from_bytes(input: &[u8]) -> Result<Self, String> {
+		if input.len() < 32 {
+			return Err("Unexpected end of checkpoint data".to_string());
+		}
+		let (previous, rest) = read_digest(&input[32..])?;
+		let (digest, rest) = read_digest(rest)?;
+		if !rest.is_empty() {
+			return Err("Checkpoint data is longer than expected".to_string());
+		}
+		Ok(Self {
+			diff_offset: vec_to_u64_be(&input[0..8]),
+			source_offset: vec_to_u64_be(&input[8..16]),
+			written: vec_to_u64_be(&input[16..24]),
+			segment_start: vec_to_u64_be(&input[24..32]),
+			previous,
+			digest,
+		})
+	}
+}
+
+/// Parses length prefixed digest, returns it with the rest of the input
+fn read_digest(input: &[u8]) -> Result<(Digest, &[u8]), String> {
+	let length = match input.first() {
+		Some(&length) => length as usize,
+		None => return Err("Unexpected end of checkpoint data".to_string()),
+	};
+	if length > MAX_DIGEST_LENGTH {
+		return Err("Checkpoint digest is too long".to_string());
+	}
+	if input.len() < 1 + length {
+		return Err("Unexpected end of checkpoint data".to_string());
+	}
+	Ok((Digest::new(&input[1..1 + length]), &input[1 + length..]))
+}
+
+/// Returns state hashing the segment which follows output with `previous` digest
+fn segment_state(previous: &Digest) -> Xxh3State {
+	let mut state = Xxh3Hasher.start();
+	state.update(previous.as_bytes());
+	state
+}
+
+impl Default for Checkpoint {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Applies diff calling `on_checkpoint` every time at least `interval` bytes are written
+///
+/// Checkpoints are taken between blocks, after the output is flushed. `on_checkpoint` should make
+/// the output durable (e.g. `File::sync_data`) before storing the checkpoint.
+/// Source, diff and output are read and written from the start. Returns final checkpoint
+pub fn apply_diff_with_checkpoints<T, U, W, F>(
+	file: &mut T,
+	diff: &mut U,
+	output: &mut W,
+	interval: u64,
+	on_checkpoint: F,
+) -> IOResult<Checkpoint>
+where
+	T: Read + Seek,
+	U: Read + Seek,
+	W: Write + Seek,
+	F: FnMut(&Checkpoint) -> IOResult<()>,
+{
+	output.seek(SeekFrom::Start(0))?;
+	apply_from(
+		file,
+		diff,
+		output,
+		&Checkpoint::new(),
+		interval,
+		on_checkpoint,
+	)
+}
+
+/// Resumes application stopped after `checkpoint`
+///
+/// Reads back the output segment between `checkpoint.segment_start` and `checkpoint.written` and
+/// fails if it doesn't match the checkpoint digest. Output before the segment is not read, it was
+/// made durable before the previous checkpoint was stored. Output bytes written after the
+/// checkpoint are overwritten, though the output is not truncated if it is longer than the target,
+/// size of the target is in the returned checkpoint
+pub fn resume_apply_diff<T, U, W, F>(
+	file: &mut T,
+	diff: &mut U,
+	output: &mut W,
+	checkpoint: &Checkpoint,
+	interval: u64,
+	on_checkpoint: F,
+) -> IOResult<Checkpoint>
+where
+	T: Read + Seek,
+	U: Read + Seek,
+	W: Read + Write + Seek,
+	F: FnMut(&Checkpoint) -> IOResult<()>,
+{
+	if checkpoint.segment_start > checkpoint.written {
+		return Err(Error::new(
+			ErrorKind::InvalidInput,
+			"Checkpoint segment starts after the written output",
+		));
+	}
+	output.seek(SeekFrom::Start(checkpoint.segment_start))?;
+	let mut state = segment_state(&checkpoint.previous);
+	let mut buf = vec![0u8; 1024 * 64];
+	let mut left = checkpoint.written - checkpoint.segment_start;
+	while left > 0 {
+		let size = min(left, buf.len() as u64) as usize;
+		let read = output.read(&mut buf[..size])?;
+		if read == 0 {
+			return Err(Error::new(
+				ErrorKind::UnexpectedEof,
+				"Output is shorter than the checkpoint",
+			));
+		}
+		state.update(&buf[..read]);
+		left -= read as u64;
+	}
+	if state.finish() != checkpoint.digest {
+		return Err(Error::new(
+			ErrorKind::InvalidData,
+			"Output doesn't match the checkpoint",
+		));
+	}
+	apply_from(file, diff, output, checkpoint, interval, on_checkpoint)
+}
+
+fn apply_from<T, U, W, F>(
+	file: &mut T,
+	diff: &mut U,
+	output: &mut W,
+	checkpoint: &Checkpoint,
+	interval: u64,
+	mut on_checkpoint: F,
+) -> IOResult<Checkpoint>
+where
+	T: Read + Seek,
+	U: Read + Seek,
+	W: Write + Seek,
+	F: FnMut(&Checkpoint) -> IOResult<()>,
+{
+	file.seek(SeekFrom::Start(checkpoint.source_offset))?;
+	diff.seek(SeekFrom::Start(checkpoint.diff_offset))?;
+	output.seek(SeekFrom::Start(checkpoint.written))?;
+	let mut source = CountingReader {
+		input: file,
+		read: checkpoint.source_offset,
+	};
+	let mut diff = CountingReader {
+		input: diff,
+		read: checkpoint.diff_offset,
+	};
+	let mut output = HashingWriter {
+		output: BufWriter::with_capacity(1024 * 64, output),
+		state: segment_state(&checkpoint.digest),
+		written: checkpoint.written,
+	};
+	let mut last = checkpoint.written;
+	let mut previous = checkpoint.digest;
+
+	while apply_next_block(&mut source, &mut diff, &mut output)? {
+		if output.written - last >= interval {
+			output.flush()?;
+			let digest = output.state.clone().finish();
+			let checkpoint = Checkpoint {
+				diff_offset: diff.read,
+				source_offset: source.read,
+				written: output.written,
+				segment_start: last,
+				previous,
+				digest,
+			};
+			on_checkpoint(&checkpoint)?;
+			output.state = segment_state(&digest);
+			last = output.written;
+			previous = digest;
+		}
+	}
+
+	output.flush()?;
+	Ok(Checkpoint {
+		diff_offset: diff.read,
+		source_offset: source.read,
+		written: output.written,
+		segment_start: last,
+		previous,
+		digest: output.state.finish(),
+	})
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+	use super::{apply_diff_with_checkpoints, resume_apply_diff, Checkpoint};
+	use diff::create_diff;
+	use std::fs::read;
+	use std::io::{Cursor, Error, ErrorKind};
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let mut diff = Cursor::new(vec![]);
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		let expected = read("./test_data/a_b.txt").unwrap();
+		let mut source = Cursor::new(read("./test_data/a_a.txt").unwrap());
+
+		let mut checkpoints = vec![];
+		let mut output = Cursor::new(vec![]);
+		let last = apply_diff_with_checkpoints(&mut source, &mut diff, &mut output, 1, |x| {
+			checkpoints.push(*x);
+			Ok(())
+		})
+		.unwrap();
+		assert_eq!(output.get_ref(), &expected);
+		assert_eq!(last.written, expected.len() as u64);
+		assert!(checkpoints.len() > 1);
+
+		let checkpoint = checkpoints[checkpoints.len() / 2];
+		assert!(checkpoint.segment_start > 0);
+		let bytes = checkpoint.to_bytes();
+		assert_eq!(Checkpoint::from_bytes(&bytes).unwrap(), checkpoint);
+		assert!(Checkpoint::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+		let mut output = Cursor::new(vec![]);
+		let result = apply_diff_with_checkpoints(&mut source, &mut diff, &mut output, 1, |x| {
+			if x.written > checkpoint.written {
+				return Err(Error::new(ErrorKind::Interrupted, "Crash"));
+			}
+			Ok(())
+		});
+		assert!(result.is_err());
+
+		let resumed =
+			resume_apply_diff(&mut source, &mut diff, &mut output, &checkpoint, 1, |_| {
+				Ok(())
+			})
+			.unwrap();
+		assert_eq!(resumed, last);
+		assert_eq!(output.get_ref(), &expected);
+
+		output.get_mut()[checkpoint.segment_start as usize] ^= 1;
+		assert!(
+			resume_apply_diff(&mut source, &mut diff, &mut output, &checkpoint, 1, |_| Ok(
+				()
+			))
+			.is_err()
+		);
+	}
+}
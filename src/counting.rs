@@ -0,0 +1,38 @@
+//! Contains reader and writer wrappers tracking transferred bytes
+
+use hasher::HasherState;
+use std::io::{Read, Result as IOResult, Write};
+
+/// Reader counting read bytes
+pub struct CountingReader<R: Read> {
+	pub input: R,
+	pub read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+		let read = self.input.read(buf)?;
+		self.read += read as u64;
+		Ok(read)
+	}
+}
+
+/// Writer hashing and counting written bytes
+pub struct HashingWriter<W: Write, S: HasherState> {
+	pub output: W,
+	pub state: S,
+	pub written: u64,
+}
+
+impl<W: Write, S: HasherState> Write for HashingWriter<W, S> {
+	fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+		let written = self.output.write(buf)?;
+		self.state.update(&buf[..written]);
+		self.written += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> IOResult<()> {
+		self.output.flush()
+	}
+}
@@ -0,0 +1,274 @@
+//! Contains push-based diff decoder
+//!
+//! `Decoder` doesn't read anything by itself, diff bytes are fed in chunks of any size and
+//! decoded into `Event`s. The end of the diff is detected with `is_complete`, so truncated diffs
+//! are told apart from diffs ending between blocks without relying on `UnexpectedEof`.
+
+use diff_block::DiffBlockN;
+use functions::vec_to_u32_be;
+use std::cmp::min;
+use std::io::{Error, ErrorKind, Read, Result as IOResult};
+
+/// Decoded part of the diff
+#[derive(Debug, PartialEq)]
+pub enum Event<'a> {
+	/// Header of the block, payload follows in `Data` events
+	Block(DiffBlockN<u32>),
+	/// Chunk of the block payload
+	Data(&'a [u8]),
+}
+
+/// Incremental decoder of the binary diff
+///
+/// ```
+/// use bin_diff::decoder::{Decoder, Event};
+///
+/// let diff = [0, 1, 0, 0, 0, 2, b'h', b'i', 0, 0, 0, 0];
+/// let mut decoder = Decoder::new();
+/// let mut data = vec![];
+/// for chunk in diff.chunks(5) {
+///     let mut chunk = &chunk[..];
+///     while !chunk.is_empty() {
+///         let (used, event) = decoder.feed(chunk).unwrap();
+///         if let Some(Event::Data(x)) = event {
+///             data.extend_from_slice(x);
+///         }
+///         chunk = &chunk[used..];
+///     }
+/// }
+/// assert_eq!(data, b"hi");
+/// assert!(!decoder.is_complete());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+	header: [u8; 10],
+	filled: usize,
+	left: u64,
+	offset: u64,
+}
+
+impl Decoder {
+	/// Creates decoder expecting header of the block
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns number of bytes consumed so far
+	pub fn offset(&self) -> u64 {
+		self.offset
+	}
+
+	/// Checks if decoder stopped between blocks
+	pub fn is_complete(&self) -> bool {
+		self.filled == 0 && self.left == 0
+	}
+
+	/// Returns action of the block whose header is decoded, once its first two bytes are fed
+	///
+	/// Tells the unknown action after `feed` fails
+	pub fn action(&self) -> Option<u16> {
+		match self.filled {
+			0 | 1 => None,
+			_ => Some(vec_to_u32_be(&self.header[0..2]) as u16),
+		}
+	}
+
+	/// Returns number of bytes completing current header or payload
+	///
+	/// Feeding at most this many bytes never crosses the block boundary
+	pub fn needed(&self) -> u64 {
+		match self.left {
+			0 => (self.header_size() - self.filled) as u64,
+			x => x,
+		}
+	}
+
+	fn header_size(&self) -> usize {
+		match self.header[0..2] {
+			[0, 3] if self.filled >= 2 => 10,
+			_ => 6,
+		}
+	}
+
+	/// Decodes bytes from the start of `input`
+	///
+	/// Returns number of consumed bytes and decoded event, if any. At most one event is
+	/// decoded, unconsumed bytes should be fed again. Decoder shouldn't be used after an error
+	pub fn feed<'a>(&mut self, input: &'a [u8]) -> IOResult<(usize, Option<Event<'a>>)> {
+		if self.left > 0 {
+			let size = min(self.left, input.len() as u64) as usize;
+			self.left -= size as u64;
+			self.offset += size as u64;
+			let event = match size {
+				0 => None,
+				_ => Some(Event::Data(&input[..size])),
+			};
+			return Ok((size, event));
+		}
+		let mut used = 0;
+		while used < input.len() {
+			let size = min(self.header_size() - self.filled, input.len() - used);
+			self.header[self.filled..self.filled + size].copy_from_slice(&input[used..used + size]);
+			self.filled += size;
+			used += size;
+			if self.filled >= 2 && vec_to_u32_be(&self.header[0..2]) > 4 {
+				return Err(Error::new(
+					ErrorKind::InvalidData,
+					"Unknown Action: possibly corrupted file or diff",
+				));
+			}
+			if self.filled == self.header_size() {
+				let block = self.parse_header();
+				self.offset += used as u64;
+				return Ok((used, Some(Event::Block(block))));
+			}
+		}
+		self.offset += used as u64;
+		Ok((used, None))
+	}
+
+	fn parse_header(&mut self) -> DiffBlockN<u32> {
+		let size = vec_to_u32_be(&self.header[2..6]);
+		let (block, left) = match self.header[1] {
+			0 => (DiffBlockN::Skip(size), 0),
+			1 => (DiffBlockN::Add(size), size),
+			2 => (DiffBlockN::Remove(size), 0),
+			3 => {
+				let add = vec_to_u32_be(&self.header[6..10]);
+				(DiffBlockN::Replace(size, add), add)
+			}
+			_ => (DiffBlockN::ReplaceWithSameLength(size), size),
+		};
+		self.filled = 0;
+		self.left = u64::from(left);
+		block
+	}
+
+	/// Skips up to `size` bytes of the block payload, returns number of skipped bytes
+	///
+	/// Used when the payload is not read, e.g. when it is seeked over
+	pub fn skip(&mut self, size: u64) -> u64 {
+		let size = min(size, self.left);
+		self.left -= size;
+		self.offset += size;
+		size
+	}
+
+	/// Reads header of the next block from `input`
+	///
+	/// Returns `None` if the input ends, `is_complete` tells whether it ended between blocks.
+	/// Payload of the previous block must be consumed first
+	pub fn read_block<T: Read>(&mut self, input: &mut T) -> IOResult<Option<DiffBlockN<u32>>> {
+		if self.left > 0 {
+			return Err(Error::new(
+				ErrorKind::InvalidInput,
+				"Payload of the previous block is not consumed",
+			));
+		}
+		let mut buf = [0u8; 10];
+		loop {
+			let size = self.needed() as usize;
+			let read = read_some(input, &mut buf[..size])?;
+			if read == 0 {
+				return Ok(None);
+			}
+			if let (_, Some(Event::Block(block))) = self.feed(&buf[..read])? {
+				return Ok(Some(block));
+			}
+		}
+	}
+
+	/// Reads next chunk of the block payload from `input` into `buf`
+	///
+	/// Returns `None` once the payload is complete or the input ends
+	pub fn read_data<'a, T: Read>(
+		&mut self,
+		input: &mut T,
+		buf: &'a mut [u8],
+	) -> IOResult<Option<&'a [u8]>> {
+		let size = min(self.left, buf.len() as u64) as usize;
+		if size == 0 {
+			return Ok(None);
+		}
+		let read = read_some(input, &mut buf[..size])?;
+		if read == 0 {
+			return Ok(None);
+		}
+		self.skip(read as u64);
+		Ok(Some(&buf[..read]))
+	}
+}
+
+/// Reads into `buf` retrying on `Interrupted`
+fn read_some<T: Read>(input: &mut T, buf: &mut [u8]) -> IOResult<usize> {
+	loop {
+		match input.read(buf) {
+			Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+			x => return x,
+		}
+	}
+}
+
+#[cfg(test)]
+mod decoder_tests {
+	use super::{Decoder, Event};
+	use diff_block::DiffBlockN;
+	use std::io::Cursor;
+
+	#[test]
+	fn feed_test() {
+		let diff = [
+			0, 3, 0, 0, 0, 1, 0, 0, 0, 2, b'a', b'b', 0, 0, 0, 0, 0, 4, 0, 2, 0, 0,
+		];
+		for chunk_size in 1..diff.len() + 1 {
+			let mut decoder = Decoder::new();
+			let mut blocks = vec![];
+			let mut data = vec![];
+			for chunk in diff.chunks(chunk_size) {
+				let mut chunk = chunk;
+				while !chunk.is_empty() {
+					let (used, event) = decoder.feed(chunk).unwrap();
+					match event {
+						Some(Event::Block(x)) => blocks.push(x),
+						Some(Event::Data(x)) => data.extend_from_slice(x),
+						None => {}
+					}
+					chunk = &chunk[used..];
+				}
+			}
+			assert_eq!(blocks, vec![DiffBlockN::Replace(1, 2), DiffBlockN::Skip(4)]);
+			assert_eq!(data, b"ab");
+			assert!(!decoder.is_complete());
+			assert_eq!(decoder.needed(), 2);
+			assert_eq!(decoder.offset(), diff.len() as u64);
+		}
+		let mut decoder = Decoder::new();
+		assert_eq!(decoder.action(), None);
+		assert!(decoder.feed(&[0, 5]).is_err());
+		assert_eq!(decoder.action(), Some(5));
+	}
+
+	#[test]
+	fn read_test() {
+		let mut input = Cursor::new(vec![0, 1, 0, 0, 0, 3, b'a', b'b', b'c', 0, 2]);
+		let mut decoder = Decoder::new();
+		let mut buf = [0u8; 2];
+		assert_eq!(
+			decoder.read_block(&mut input).unwrap(),
+			Some(DiffBlockN::Add(3))
+		);
+		assert!(decoder.read_block(&mut input).is_err());
+		assert_eq!(
+			decoder.read_data(&mut input, &mut buf).unwrap(),
+			Some(&b"ab"[..])
+		);
+		assert_eq!(
+			decoder.read_data(&mut input, &mut buf).unwrap(),
+			Some(&b"c"[..])
+		);
+		assert_eq!(decoder.read_data(&mut input, &mut buf).unwrap(), None);
+		assert!(decoder.is_complete());
+		assert_eq!(decoder.read_block(&mut input).unwrap(), None);
+		assert!(!decoder.is_complete());
+	}
+}
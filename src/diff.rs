@@ -1,14 +1,24 @@
 //! Functions for creating, applying and combining diffs
 
 use bytes_serializer::IntoBytesSerializer;
-use diff_block::DiffBlock;
+use decoder::Decoder;
+use diff_block::{DiffBlock, DiffBlockN};
+use diff_index::DiffIndex;
 use diff_iterator::DiffIterator;
 use diff_reader::DiffReader;
-use drain::Drainable;
-use functions::{read_n, vec_shift, vec_to_u32_be};
+use diff_writer::DiffWriter;
+use functions::vec_shift;
+use hasher::{BlockHasher, HashAlgorithm};
 use indexes::WithIndexes;
-use readslice::ReadSlice;
-use std::io::{copy, BufWriter, Error, ErrorKind, Read, Result as IOResult, Seek, Write};
+use monitor::{Monitor, ObservedFile, ObservedWriter};
+use progress::{CancellationToken, Observer, Phase};
+use readslice::{ReadSlice, SendReadSlice, Sharing};
+use segments::{Segment, Segments};
+use signature::Signature;
+use std::cmp::min;
+use std::io::{
+	copy, sink, BufWriter, Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom, Write,
+};
 
 /// Creates and writes diff of two `WithIndexes` Implementations
 pub fn create_diff<T: WithIndexes, U: WithIndexes, W: Write>(
@@ -23,18 +33,246 @@ pub fn create_diff<T: WithIndexes, U: WithIndexes, W: Write>(
 		))
 	})?;
 
-	let mut stdo = BufWriter::with_capacity(1024 * 64, output);
+	write_diff(&mut dit, output)
+}
+
+/// Same as `create_diff`, but reports progress to `observer` and stops once `token` is cancelled
+pub fn create_diff_observed<T, U, W, O>(
+	original: &mut T,
+	edited: &mut U,
+	output: &mut W,
+	observer: &mut O,
+	token: &CancellationToken,
+) -> IOResult<()>
+where
+	T: WithIndexes,
+	U: WithIndexes,
+	W: Write,
+	O: Observer,
+{
+	let monitor = Monitor::new(observer, token, Phase::Hashing);
+	monitor.check()?;
+	let original = ObservedFile {
+		file: original,
+		monitor: &monitor,
+	};
+	let edited = ObservedFile {
+		file: edited,
+		monitor: &monitor,
+	};
+	let mut dit = DiffIterator::new(original, edited)
+		.map_err(|_| Error::new(ErrorKind::InvalidData, "Error while creating DiffIterator"))?;
+	monitor.check()?;
+	monitor.update(|x| x.phase = Phase::Writing);
 
+	let mut writer = DiffWriter::new(ObservedWriter {
+		output: BufWriter::with_capacity(1024 * 64, output),
+		monitor: &monitor,
+	});
 	while let Some(block) = dit.next_ref() {
-		let mut block = block
-			.or_else(|_| Err(Error::new(ErrorKind::Other, "Cannot get diff block")))
-			.map(|x| x.into_bytes())?;
-		copy(&mut block, &mut stdo)?;
+		monitor.check()?;
+		let block =
+			block.map_err(|_| Error::new(ErrorKind::InvalidData, "Cannot get diff block"))?;
+		writer.write_block(block)?;
+		monitor.update(|x| x.blocks += 1);
 	}
-	stdo.flush()?;
+	writer.finish()?;
 	Ok(())
 }
 
+/// Same as `create_diff`, but also returns index of the written diff (see `DiffIndex`)
+pub fn create_diff_with_index<T: WithIndexes, U: WithIndexes, W: Write>(
+	original: &mut T,
+	edited: &mut U,
+	output: &mut W,
+) -> IOResult<DiffIndex> {
+	let mut dit = DiffIterator::new(original, edited)
+		.map_err(|_| Error::new(ErrorKind::InvalidData, "Error while creating DiffIterator"))?;
+	let mut writer = DiffWriter::new(BufWriter::with_capacity(1024 * 64, output));
+	let mut index = DiffIndex::new();
+
+	while let Some(block) = dit.next_ref() {
+		let block =
+			block.map_err(|_| Error::new(ErrorKind::InvalidData, "Cannot get diff block"))?;
+		index.push_block(&block);
+		writer.write_block(block)?;
+	}
+	writer.finish()?;
+	Ok(index)
+}
+
+/// Creates and writes diff of two `WithIndexes` Implementations comparing blocks with given hasher
+pub fn create_diff_with_hasher<T: WithIndexes, U: WithIndexes, W: Write, H: BlockHasher>(
+	original: &mut T,
+	edited: &mut U,
+	output: &mut W,
+	hasher: H,
+) -> IOResult<()> {
+	let mut dit = DiffIterator::with_hasher(original, edited, hasher)
+		.map_err(|_| Error::new(ErrorKind::InvalidData, "Error while creating DiffIterator"))?;
+
+	write_diff(&mut dit, output)
+}
+
+/// Creates and writes diff of two `WithIndexes` Implementations hashing blocks on multiple threads
+///
+/// `open_original` and `open_edited` are called once per hashing thread and must return independent
+/// instances of the same file (see `lines_with_hash_iterator::hash_lines_parallel`).
+/// `threads` equal to `0` means number of available cores. Output is identical to `create_diff_with_hasher`
+pub fn create_diff_parallel<T, U, F, G, W, H>(
+	open_original: F,
+	open_edited: G,
+	output: &mut W,
+	hasher: H,
+	threads: usize,
+) -> IOResult<()>
+where
+	T: WithIndexes,
+	U: WithIndexes,
+	F: Fn() -> IOResult<T> + Sync,
+	G: Fn() -> IOResult<U> + Sync,
+	W: Write,
+	H: BlockHasher + Sync,
+{
+	let mut dit = DiffIterator::new_parallel(open_original, open_edited, hasher, threads)
+		.map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+	write_diff(&mut dit, output)
+}
+
+/// Creates and writes diff of original file, represented by its `Signature`, and `WithIndexes` implementation
+///
+/// Only edited file is read, which is useful when the same original is diffed many times.
+/// Signature must be computed with one of built-in hashers (see `HashAlgorithm`),
+/// for custom hashers use `create_diff_from_signature_with_hasher`
+pub fn create_diff_from_signature<U: WithIndexes, W: Write>(
+	signature: &Signature,
+	edited: &mut U,
+	output: &mut W,
+) -> IOResult<()> {
+	let hasher = HashAlgorithm::from_name(signature.hasher()).ok_or_else(|| {
+		Error::new(
+			ErrorKind::InvalidInput,
+			format!("Unknown hasher \"{}\"", signature.hasher()),
+		)
+	})?;
+	create_diff_from_signature_with_hasher(signature, edited, output, hasher)
+}
+
+/// Creates and writes diff of original file, represented by its `Signature`, and `WithIndexes` implementation
+///
+/// `hasher` must be the same signature was computed with
+pub fn create_diff_from_signature_with_hasher<U: WithIndexes, W: Write, H: BlockHasher>(
+	signature: &Signature,
+	edited: &mut U,
+	output: &mut W,
+	hasher: H,
+) -> IOResult<()> {
+	let mut dit = DiffIterator::new_from_signature(signature, edited, hasher)
+		.map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+	write_diff(&mut dit, output)
+}
+
+fn write_diff<T: WithIndexes, W: Write>(dit: &mut DiffIterator<T>, output: &mut W) -> IOResult<()> {
+	let mut writer = DiffWriter::new(BufWriter::with_capacity(1024 * 64, output));
+
+	while let Some(block) = dit.next_ref() {
+		let block =
+			block.or_else(|_| Err(Error::new(ErrorKind::Other, "Cannot get diff block")))?;
+		writer.write_block(block)?;
+	}
+	writer.finish()?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod create_diff_parallel_tests {
+	use super::{create_diff, create_diff_parallel};
+	use hasher::Sha256Hasher;
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let pairs = [
+			["./test_data/a_a.txt", "./test_data/a_b.txt"],
+			["./test_data/a_c.txt", "./test_data/a_d.txt"],
+		];
+		for pair in pairs.iter() {
+			let mut fa = TextFile::from_path(pair[0]);
+			let mut fb = TextFile::from_path(pair[1]);
+			let mut diff = vec![];
+			create_diff(&mut fa, &mut fb, &mut diff).unwrap();
+
+			let mut pdiff = vec![];
+			create_diff_parallel(
+				|| Ok(TextFile::from_path(pair[0])),
+				|| Ok(TextFile::from_path(pair[1])),
+				&mut pdiff,
+				Sha256Hasher,
+				4,
+			)
+			.unwrap();
+
+			assert_eq!(diff, pdiff);
+		}
+	}
+}
+
+#[cfg(test)]
+mod create_diff_from_signature_tests {
+	use super::{
+		create_diff, create_diff_from_signature, create_diff_from_signature_with_hasher,
+		create_diff_with_hasher,
+	};
+	use hasher::{Sha256Hasher, Xxh3Hasher};
+	use signature::Signature;
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let signature = Signature::new(TextFile::from_path("./test_data/a_a.txt")).unwrap();
+		let signature = Signature::from_bytes(&signature.to_bytes()).unwrap();
+
+		for path in ["./test_data/a_b.txt", "./test_data/a_c.txt"].iter() {
+			let mut fa = TextFile::from_path("./test_data/a_a.txt");
+			let mut fb = TextFile::from_path(path);
+			let mut diff = vec![];
+			create_diff(&mut fa, &mut fb, &mut diff).unwrap();
+
+			let mut fb = TextFile::from_path(path);
+			let mut sdiff = vec![];
+			create_diff_from_signature(&signature, &mut fb, &mut sdiff).unwrap();
+
+			assert_eq!(diff, sdiff);
+		}
+	}
+
+	#[test]
+	fn hasher_test() {
+		let signature =
+			Signature::with_hasher(TextFile::from_path("./test_data/a_a.txt"), Xxh3Hasher).unwrap();
+
+		let mut fa = TextFile::from_path("./test_data/a_a.txt");
+		let mut fb = TextFile::from_path("./test_data/a_b.txt");
+		let mut diff = vec![];
+		create_diff_with_hasher(&mut fa, &mut fb, &mut diff, Xxh3Hasher).unwrap();
+
+		let mut fb = TextFile::from_path("./test_data/a_b.txt");
+		let mut sdiff = vec![];
+		create_diff_from_signature(&signature, &mut fb, &mut sdiff).unwrap();
+		assert_eq!(diff, sdiff);
+
+		let mut fb = TextFile::from_path("./test_data/a_b.txt");
+		let res =
+			create_diff_from_signature_with_hasher(&signature, &mut fb, &mut sdiff, Sha256Hasher);
+		assert_eq!(
+			res.unwrap_err().to_string(),
+			"Signature was computed with \"xxh3-128\" hasher, got \"sha256\""
+		);
+	}
+}
+
 /// Returns size in bytes of prospective diff
 pub fn measure_diff_size<T: WithIndexes, U: WithIndexes>(
 	original: &mut T,
@@ -72,59 +310,60 @@ mod measure_diff_size_tests {
 /// Takes file, applies diffblock and writes to output
 ///
 /// Notice, that result of this function is not finalized diff, but output with only one operation applied.
-/// For the whole diff use `apply_diff`
+/// For the whole diff use `apply_diff`. Fails with `UnexpectedEof` if the block is not applied completely,
+/// see `apply_next_block`
 pub fn apply_diffblock<T: Read, U: Read, W: Write>(
-	mut file: &mut T,
+	file: &mut T,
 	block: &mut U,
-	mut output: &mut W,
+	output: &mut W,
 ) -> IOResult<()> {
-	let mut buf = vec![0; 4];
-
-	read_n(block, &mut buf, 2)?;
-	let action: &[u8] = &buf[0..2].to_vec();
-	match action {
-		[0x00, 0x00] => {
-			read_n(block, &mut buf, 4)?;
-			let len = vec_to_u32_be(&buf[0..4]);
-			let mut r = (&mut file).take(u64::from(len));
-			copy(&mut r, &mut output)?;
-		}
-		[0x00, 0x01] => {
-			read_n(block, &mut buf, 4)?;
-			let len = vec_to_u32_be(&buf[0..4]);
-			let mut r = block.take(u64::from(len));
-			copy(&mut r, &mut output)?;
-		}
-		[0x00, 0x02] => {
-			read_n(block, &mut buf, 4)?;
-			let len = vec_to_u32_be(&buf[0..4]);
-			file.drain(u64::from(len)).get_drained()?;
-		}
-		[0x00, 0x03] => {
-			read_n(block, &mut buf, 4)?;
-			let remove = vec_to_u32_be(&buf[0..4]);
-			read_n(block, &mut buf, 4)?;
-			let add = vec_to_u32_be(&buf[0..4]);
-			file.drain(u64::from(remove)).get_drained()?;
-			let mut r = block.take(u64::from(add));
-			copy(&mut r, &mut output)?;
-		}
-		[0x00, 0x04] => {
-			read_n(block, &mut buf, 4)?;
-			let size = vec_to_u32_be(&buf[0..4]);
-			file.drain(u64::from(size)).get_drained()?;
-			let mut r = block.take(u64::from(size));
-			copy(&mut r, &mut output)?;
-		}
-		_ => {
+	if apply_next_block(file, block, output)? {
+		Ok(())
+	} else {
+		Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected EOF"))
+	}
+}
+
+/// Applies next block of the diff, returns `false` once the diff ends
+///
+/// Fails with `ErrorKind::UnexpectedEof` if the diff ends in the middle of the block.
+/// Source shorter than the diff expects is not reported, `false` is returned once the block
+/// removes bytes past the end of the source. Exactly one block is read from the diff
+pub fn apply_next_block<T: Read, U: Read, W: Write>(
+	file: &mut T,
+	diff: &mut U,
+	output: &mut W,
+) -> IOResult<bool> {
+	let mut decoder = Decoder::new();
+	let (from_source, remove) = match decoder.read_block(diff)? {
+		Some(DiffBlockN::Skip(size)) => (size, 0),
+		Some(DiffBlockN::Add(_)) => (0, 0),
+		Some(DiffBlockN::Remove(size))
+		| Some(DiffBlockN::Replace(size, _))
+		| Some(DiffBlockN::ReplaceWithSameLength(size)) => (0, size),
+		None if decoder.is_complete() => return Ok(false),
+		None => {
 			return Err(Error::new(
-				ErrorKind::Other,
-				"Unknown Action: possibly corrupted file or diff",
-			));
+				ErrorKind::UnexpectedEof,
+				"Diff is truncated in block header",
+			))
 		}
 	};
-
-	Ok(())
+	copy(&mut file.take(u64::from(from_source)), output)?;
+	if copy(&mut file.take(u64::from(remove)), &mut sink())? < u64::from(remove) {
+		return Ok(false);
+	}
+	let mut buf = [0u8; 1024 * 8];
+	while let Some(data) = decoder.read_data(diff, &mut buf)? {
+		output.write_all(data)?;
+	}
+	if !decoder.is_complete() {
+		return Err(Error::new(
+			ErrorKind::UnexpectedEof,
+			"Diff is truncated in block data",
+		));
+	}
+	Ok(true)
 }
 
 /// Takes file and applies binary diff
@@ -135,14 +374,31 @@ pub fn apply_diff<T: Read, U: Read, W: Write>(
 ) -> IOResult<()> {
 	let mut output = BufWriter::with_capacity(1024 * 64, &mut output);
 
+	while apply_next_block(&mut file, &mut diff, &mut output)? {}
+
+	output.flush()
+}
+
+/// Same as `apply_diff`, but reports progress to `observer` and stops once `token` is cancelled
+pub fn apply_diff_observed<T: Read, U: Read, W: Write, O: Observer>(
+	file: &mut T,
+	diff: &mut U,
+	output: &mut W,
+	observer: &mut O,
+	token: &CancellationToken,
+) -> IOResult<()> {
+	let monitor = Monitor::new(observer, token, Phase::Applying);
+	let mut output = ObservedWriter {
+		output: BufWriter::with_capacity(1024 * 64, output),
+		monitor: &monitor,
+	};
+
 	loop {
-		let res = apply_diffblock(&mut file, &mut diff, &mut output);
-		if let Err(err) = res {
-			match err.kind() {
-				ErrorKind::UnexpectedEof => break,
-				_ => return Err(err),
-			};
+		monitor.check()?;
+		if !apply_next_block(file, diff, &mut output)? {
+			break;
 		}
+		monitor.update(|x| x.blocks += 1);
 	}
 
 	output.flush()
@@ -152,7 +408,7 @@ pub fn apply_diff<T: Read, U: Read, W: Write>(
 mod apply_diff_tests {
 	use super::{apply_diff, create_diff};
 	use functions::compute_hash;
-	use std::io::{Cursor, Seek, SeekFrom};
+	use std::io::{Cursor, ErrorKind, Seek, SeekFrom};
 	use test_mod::TextFile;
 
 	#[test]
@@ -247,6 +503,24 @@ mod apply_diff_tests {
 		)
 	}
 
+	#[test]
+	fn truncated_test() {
+		let file = b"0123456789";
+		let diff = [0, 0, 0, 0, 0, 4, 0, 1, 0, 0, 0, 3, b'a', b'b', b'c'];
+
+		let mut output = vec![];
+		apply_diff(&mut Cursor::new(file), &mut Cursor::new(&diff), &mut output).unwrap();
+		assert_eq!(output, b"0123abc");
+		for size in [3, 8, diff.len() - 1].iter() {
+			let res = apply_diff(
+				&mut Cursor::new(file),
+				&mut Cursor::new(&diff[..*size]),
+				&mut vec![],
+			);
+			assert_eq!(res.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+		}
+	}
+
 	#[test]
 	fn works_live_test() {
 		#[cfg_attr(rustfmt, rustfmt_skip)]
@@ -282,20 +556,119 @@ mod apply_diff_tests {
 	}
 }
 
-fn combine_diffs_to_vec<'a, 'b: 'a>(
-	mut blocksa: DiffReader<'a, 'b>,
-	mut blocksb: DiffReader<'a, 'b>,
-) -> IOResult<Vec<DiffBlock<'b, u32>>> {
+/// Applies diff partially, writing only target bytes in `[start, end)` range
+///
+/// Only source and diff data of the range are read, other blocks are skipped over.
+/// Range is clamped to the target size, returns number of written bytes
+pub fn apply_diff_range<T: Read + Seek, U: Read + Seek, W: Write>(
+	file: &mut T,
+	diff: &mut U,
+	start: u64,
+	end: u64,
+	output: &mut W,
+) -> IOResult<u64> {
+	if start >= end {
+		return Ok(0);
+	}
+	let mut input = ReadSlice::new(diff)?;
+	let mut segments = Segments::new(DiffReader::new(&mut input));
+	let mut position = 0;
+	let mut written = 0;
+	while position < end {
+		let segment = match segments.next_segment()? {
+			Some(segment) => segment,
+			None => break,
+		};
+		let size = segment.size();
+		if position + size > start {
+			let from = start.saturating_sub(position);
+			let to = min(size, end - position);
+			written += match segment {
+				Segment::Source { offset, .. } => {
+					file.seek(SeekFrom::Start(offset + from))?;
+					copy(&mut file.by_ref().take(to - from), output)?
+				}
+				Segment::Literal(data) => copy(&mut data.range(from, to - from), output)?,
+			};
+		}
+		position += size;
+	}
+	Ok(written)
+}
+
+#[cfg(test)]
+mod apply_diff_range_tests {
+	use super::{apply_diff, apply_diff_range, create_diff};
+	use std::cmp::{max, min};
+	use std::io::Cursor;
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let mut diff = Cursor::new(vec![]);
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_d.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		let mut target = vec![];
+		diff.set_position(0);
+		apply_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut diff,
+			&mut target,
+		)
+		.unwrap();
+
+		let size = target.len() as u64;
+		let ranges = [
+			(0, 1),
+			(0, size),
+			(3, 40),
+			(size / 2, size),
+			(size - 1, size + 10),
+			(size, size + 1),
+			(5, 5),
+			(7, 2),
+		];
+		for (start, end) in ranges.iter() {
+			let mut out = vec![];
+			diff.set_position(0);
+			let written = apply_diff_range(
+				&mut TextFile::from_path("./test_data/a_a.txt"),
+				&mut diff,
+				*start,
+				*end,
+				&mut out,
+			)
+			.unwrap();
+			let from = min(*start, size);
+			let to = max(from, min(*end, size));
+			let expected = &target[from as usize..to as usize];
+			assert_eq!(written, expected.len() as u64);
+			assert_eq!(out, expected, "range {}..{}", start, end);
+		}
+	}
+}
+
+/// Combines two diffs calling `on_block` with number of combined blocks before every step
+fn combine_diffs_to_vec<'a, 'b: 'a, K: Sharing, F: FnMut(usize) -> IOResult<()>>(
+	mut blocksa: DiffReader<'a, 'b, K>,
+	mut blocksb: DiffReader<'a, 'b, K>,
+	on_block: &mut F,
+) -> IOResult<Vec<DiffBlock<'b, u32, K>>> {
 	let mut out = vec![];
 	let mut da = None;
 	let mut db = None;
 
 	loop {
+		on_block(out.len())?;
 		if da.is_none() {
-			da = blocksa.next()?;
+			da = blocksa.next_block()?;
 		}
 		if db.is_none() {
-			db = blocksb.next()?;
+			db = blocksb.next_block()?;
 		}
 		match (da.is_none(), db.is_none()) {
 			(true, true) => break,
@@ -335,6 +708,7 @@ fn combine_diffs_to_vec<'a, 'b: 'a>(
 		let mut blocka = out.remove(0);
 		let len = out.len();
 		for i in 0..len {
+			on_block(processed.len())?;
 			let mut blockb = out.remove(0);
 			let op = blocka + blockb;
 			processed.push(op.0);
@@ -367,17 +741,13 @@ pub fn combine_diffs<'a, T: 'a + Read + Seek, U: 'a + Read + Seek, W: Write>(
 	blocksb: U,
 	mut output: &mut W,
 ) -> IOResult<()> {
-	let mut blocksa = ReadSlice::new(blocksa);
+	let mut blocksa = ReadSlice::new(blocksa)?;
 	let blocksa = DiffReader::new(&mut blocksa);
-	let mut blocksb = ReadSlice::new(blocksb);
+	let mut blocksb = ReadSlice::new(blocksb)?;
 	let blocksb = DiffReader::new(&mut blocksb);
-	let blocks = combine_diffs_to_vec(blocksa, blocksb)?;
+	let blocks = combine_diffs_to_vec(blocksa, blocksb, &mut |_| Ok(()))?;
 
-	for block in blocks {
-		copy(&mut block.into_bytes(), &mut output)?;
-	}
-
-	Ok(())
+	DiffWriter::new(&mut output).write_blocks(blocks)
 }
 
 #[cfg(test)]
@@ -440,9 +810,16 @@ mod combine_diffs_tests {
 	}
 }
 
-fn combine_diffs_vec_to_vec<'a, T: 'a + Read + Seek>(
+fn combine_diffs_vec_to_vec<'a, T, K, F>(
 	mut diffs: &mut Vec<T>,
-) -> IOResult<Vec<DiffBlock<'a, u32>>> {
+	slice: fn(T) -> IOResult<ReadSlice<'a, K>>,
+	mut on_block: F,
+) -> IOResult<Vec<DiffBlock<'a, u32, K>>>
+where
+	T: 'a + Read + Seek,
+	K: Sharing,
+	F: FnMut(usize) -> IOResult<()>,
+{
 	if diffs.len() < 2 {
 		return Err(Error::new(
 			ErrorKind::InvalidInput,
@@ -452,20 +829,20 @@ fn combine_diffs_vec_to_vec<'a, T: 'a + Read + Seek>(
 
 	let mut out = {
 		let diffsa = diffs.remove(0);
-		let mut sla = ReadSlice::new(diffsa);
+		let mut sla = slice(diffsa)?;
 		let blocksa = DiffReader::new(&mut sla);
 		let diffsb = diffs.remove(0);
-		let mut slb = ReadSlice::new(diffsb);
+		let mut slb = slice(diffsb)?;
 		let blocksb = DiffReader::new(&mut slb);
-		combine_diffs_to_vec(blocksa, blocksb)?
+		combine_diffs_to_vec(blocksa, blocksb, &mut on_block)?
 	};
 
 	while let Some(block) = vec_shift(&mut diffs) {
 		out = {
 			let blocksa = DiffReader::new_from_vector(&mut out);
-			let mut slb = ReadSlice::new(block);
+			let mut slb = slice(block)?;
 			let blocksb = DiffReader::new(&mut slb);
-			combine_diffs_to_vec(blocksa, blocksb)?
+			combine_diffs_to_vec(blocksa, blocksb, &mut on_block)?
 		}
 	}
 
@@ -478,7 +855,19 @@ fn combine_diffs_vec_to_vec<'a, T: 'a + Read + Seek>(
 pub fn combine_diffs_vec_to_diffblocks<'a, 'b: 'a, T: 'b + Read + Seek>(
 	diffs: &'a mut Vec<T>,
 ) -> IOResult<Vec<impl Read + 'b>> {
-	let mut blocks = combine_diffs_vec_to_vec(diffs)?;
+	let mut blocks = combine_diffs_vec_to_vec(diffs, ReadSlice::new, |_| Ok(()))?;
+	let mut reads = vec![];
+	while let Some(item) = vec_shift(&mut blocks) {
+		reads.push(item.into_bytes());
+	}
+	Ok(reads)
+}
+
+/// Same as `combine_diffs_vec_to_diffblocks`, but returned blocks can be sent to other threads
+pub fn combine_diffs_vec_to_diffblocks_send<'a, 'b: 'a, T: 'b + Read + Seek + Send>(
+	diffs: &'a mut Vec<T>,
+) -> IOResult<Vec<impl Read + Send + 'b>> {
+	let mut blocks = combine_diffs_vec_to_vec(diffs, SendReadSlice::new_send, |_| Ok(()))?;
 	let mut reads = vec![];
 	while let Some(item) = vec_shift(&mut blocks) {
 		reads.push(item.into_bytes());
@@ -500,11 +889,176 @@ pub fn combine_diffs_vec<'a, T: 'a + Read + Seek, W: Write>(
 	Ok(())
 }
 
+/// Same as `combine_diffs_vec`, but reports progress to `observer` and stops once `token` is cancelled
+pub fn combine_diffs_vec_observed<T: Read + Seek, W: Write, O: Observer>(
+	diffs: &mut Vec<T>,
+	output: &mut W,
+	observer: &mut O,
+	token: &CancellationToken,
+) -> IOResult<()> {
+	let monitor = Monitor::new(observer, token, Phase::Combining);
+	monitor.check()?;
+	let mut blocks = combine_diffs_vec_to_vec(diffs, ReadSlice::new, |blocks| {
+		monitor.update(|x| x.blocks = blocks as u64);
+		monitor.check()
+	})?;
+	monitor.update(|x| {
+		x.phase = Phase::Writing;
+		x.blocks = 0;
+	});
+	let mut output = ObservedWriter {
+		output,
+		monitor: &monitor,
+	};
+	while let Some(block) = vec_shift(&mut blocks) {
+		monitor.check()?;
+		copy(&mut block.into_bytes(), &mut output)?;
+		monitor.update(|x| x.blocks += 1);
+	}
+	output.flush()
+}
+
+#[cfg(test)]
+mod observed_tests {
+	use super::{
+		apply_diff, apply_diff_observed, combine_diffs_vec_observed, create_diff,
+		create_diff_observed,
+	};
+	use progress::{CancellationToken, Phase, Progress};
+	use std::io::{Cursor, ErrorKind};
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let token = CancellationToken::new();
+		let mut events: Vec<Progress> = vec![];
+		let mut diff = vec![];
+		create_diff_observed(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+			&mut |x: &Progress| events.push(*x),
+			&token,
+		)
+		.unwrap();
+		let mut expected = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut expected,
+		)
+		.unwrap();
+		assert_eq!(diff, expected);
+		let last = events.last().unwrap();
+		assert_eq!(last.phase, Phase::Writing);
+		assert!(last.bytes_hashed > 0 && last.blocks > 0);
+		assert_eq!(last.bytes_written, diff.len() as u64);
+
+		let mut events: Vec<Progress> = vec![];
+		let mut output = vec![];
+		apply_diff_observed(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut Cursor::new(&diff),
+			&mut output,
+			&mut |x: &Progress| events.push(*x),
+			&token,
+		)
+		.unwrap();
+		let mut expected = vec![];
+		apply_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut Cursor::new(&diff),
+			&mut expected,
+		)
+		.unwrap();
+		assert_eq!(output, expected);
+		let last = events.last().unwrap();
+		assert_eq!(last.bytes_written, output.len() as u64);
+		assert!(events.len() as u64 <= last.blocks + 1);
+
+		let mut diffs = vec![Cursor::new(diff.clone()), Cursor::new(diff.clone())];
+		let mut combined = vec![];
+		combine_diffs_vec_observed(&mut diffs, &mut combined, &mut |_: &Progress| {}, &token)
+			.unwrap();
+		assert!(!combined.is_empty());
+	}
+
+	#[test]
+	fn cancel_test() {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+
+		let token = CancellationToken::new();
+		let cancel = token.clone();
+		let mut blocks = 0;
+		let result = apply_diff_observed(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut Cursor::new(&diff),
+			&mut vec![],
+			&mut |x: &Progress| {
+				blocks = x.blocks;
+				if x.blocks == 1 {
+					cancel.cancel();
+				}
+			},
+			&token,
+		);
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::Interrupted);
+		assert_eq!(blocks, 1);
+
+		let result = create_diff_observed(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut vec![],
+			&mut |_: &Progress| {},
+			&token,
+		);
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::Interrupted);
+	}
+
+	#[test]
+	fn cancel_combining_test() {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+
+		let token = CancellationToken::new();
+		let cancel = token.clone();
+		let mut events: Vec<Progress> = vec![];
+		let mut diffs = vec![Cursor::new(diff.clone()), Cursor::new(diff)];
+		let result = combine_diffs_vec_observed(
+			&mut diffs,
+			&mut vec![],
+			&mut |x: &Progress| {
+				events.push(*x);
+				if x.blocks == 1 {
+					cancel.cancel();
+				}
+			},
+			&token,
+		);
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::Interrupted);
+		let last = events.last().unwrap();
+		assert_eq!(last.phase, Phase::Combining);
+		assert_eq!(last.blocks, 1);
+	}
+}
+
 #[cfg(test)]
 mod combine_diffs_vec_tests {
-	use super::{apply_diff, combine_diffs_vec, create_diff};
+	use super::{apply_diff, combine_diffs_vec, combine_diffs_vec_to_diffblocks_send, create_diff};
 	use functions::compute_hash;
-	use std::io::{Cursor, Seek, SeekFrom};
+	use std::io::{copy, Cursor, Seek, SeekFrom};
+	use std::thread::spawn;
 	use test_mod::TextFile;
 
 	#[test]
@@ -543,4 +1097,331 @@ mod combine_diffs_vec_tests {
 
 		assert_eq!(hash, restoredhash);
 	}
+
+	#[test]
+	fn threads_test() {
+		let files = [
+			("./test_data/a_a.txt", "./test_data/a_b.txt"),
+			("./test_data/a_b.txt", "./test_data/a_c.txt"),
+			("./test_data/a_c.txt", "./test_data/a_d.txt"),
+		];
+
+		let diffs: Vec<_> = files
+			.iter()
+			.map(|&(a, b)| {
+				spawn(move || {
+					let mut out = Cursor::new(vec![]);
+					let mut filea = TextFile::from_path(a);
+					let mut fileb = TextFile::from_path(b);
+					create_diff(&mut filea, &mut fileb, &mut out).unwrap();
+					out.seek(SeekFrom::Start(0)).unwrap();
+					out
+				})
+			})
+			.collect();
+		let mut diffs = diffs.into_iter().map(|x| x.join().unwrap()).collect();
+
+		let blocks = spawn(move || combine_diffs_vec_to_diffblocks_send(&mut diffs).unwrap())
+			.join()
+			.unwrap();
+		let mut acc_diff = Cursor::new(vec![]);
+		for mut block in blocks {
+			copy(&mut block, &mut acc_diff).unwrap();
+		}
+		acc_diff.seek(SeekFrom::Start(0)).unwrap();
+
+		let mut original = TextFile::from_path("./test_data/a_a.txt");
+		let hash = compute_hash(&mut TextFile::from_path(files[files.len() - 1].1));
+		let restoredhash = {
+			let mut x = Cursor::new(vec![]);
+			apply_diff(&mut original, &mut acc_diff, &mut x).unwrap();
+			x.seek(SeekFrom::Start(0)).unwrap();
+			compute_hash(&mut x)
+		};
+
+		assert_eq!(hash, restoredhash);
+	}
+}
+
+/// Rewrites diff into its canonical form
+///
+/// Zero-length blocks are dropped, replaces are rewritten into the simplest block with the same effect
+/// and adjacent blocks are merged by `DiffBlock` `Add` rules, as long as merged sizes fit into `u32`
+pub fn normalize_diff<'a, T: 'a + Read + Seek, W: Write>(diff: T, output: &mut W) -> IOResult<()> {
+	let mut input = ReadSlice::new(diff)?;
+	let mut reader = DiffReader::new(&mut input);
+	let mut writer = DiffWriter::new(output);
+	while let Some(block) = reader.next_block()? {
+		writer.append(block)?;
+	}
+	writer.finish()?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod normalize_diff_tests {
+	use super::{apply_diff, create_diff, normalize_diff};
+	use diff_block::DiffBlock;
+	use diff_writer::DiffWriter;
+	use readslice::ReadSlice;
+	use std::io::Cursor;
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let mut writer = DiffWriter::new(vec![]);
+		let blocks = vec![
+			DiffBlock::Skip { size: 0 },
+			DiffBlock::Skip { size: 2 },
+			DiffBlock::Skip { size: 3 },
+			DiffBlock::Remove { size: 2 },
+			DiffBlock::Add {
+				data: ReadSlice::from(b"ab".to_vec()),
+			},
+			DiffBlock::Add {
+				data: ReadSlice::from(vec![]),
+			},
+			DiffBlock::Remove { size: 1 },
+			DiffBlock::Add {
+				data: ReadSlice::from(b"c".to_vec()),
+			},
+			DiffBlock::Skip { size: 1 },
+			DiffBlock::Replace {
+				remove_size: 0,
+				data: ReadSlice::from(b"d".to_vec()),
+			},
+			DiffBlock::Remove { size: u32::MAX },
+			DiffBlock::Remove { size: 1 },
+		];
+		writer.write_blocks(blocks).unwrap();
+		let diff = writer.finish().unwrap();
+
+		let mut normalized = vec![];
+		normalize_diff(Cursor::new(diff), &mut normalized).unwrap();
+
+		let mut expected = DiffWriter::new(vec![]);
+		let blocks = vec![
+			DiffBlock::Skip { size: 5 },
+			DiffBlock::ReplaceWithSameLength {
+				data: ReadSlice::from(b"ab".to_vec()),
+			},
+			DiffBlock::ReplaceWithSameLength {
+				data: ReadSlice::from(b"c".to_vec()),
+			},
+			DiffBlock::Skip { size: 1 },
+			DiffBlock::Add {
+				data: ReadSlice::from(b"d".to_vec()),
+			},
+			DiffBlock::Remove { size: u32::MAX },
+			DiffBlock::Remove { size: 1 },
+		];
+		expected.write_blocks(blocks).unwrap();
+		assert_eq!(normalized, expected.finish().unwrap());
+	}
+
+	#[test]
+	fn works_live_test() {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_d.txt"),
+			&mut diff,
+		)
+		.unwrap();
+
+		let mut normalized = vec![];
+		normalize_diff(Cursor::new(diff.clone()), &mut normalized).unwrap();
+		let mut twice = vec![];
+		normalize_diff(Cursor::new(normalized.clone()), &mut twice).unwrap();
+		assert_eq!(normalized, twice);
+
+		let mut restored = vec![];
+		apply_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut Cursor::new(diff),
+			&mut restored,
+		)
+		.unwrap();
+		let mut restored_normalized = vec![];
+		apply_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut Cursor::new(normalized),
+			&mut restored_normalized,
+		)
+		.unwrap();
+		assert_eq!(restored, restored_normalized);
+	}
+}
+
+/// Finds first target offset at which outputs of two diffs may differ
+///
+/// Diffs are compared by what they produce rather than by their blocks: each target byte is either
+/// a copy of some source byte or a byte stored in the diff. Returns `None` if both diffs produce
+/// the same output for any source
+pub fn first_difference<'a, T: 'a + Read + Seek, U: 'a + Read + Seek>(
+	diff_a: T,
+	diff_b: U,
+) -> IOResult<Option<u64>> {
+	let mut input_a = ReadSlice::new(diff_a)?;
+	let mut segments_a = Segments::new(DiffReader::new(&mut input_a));
+	let mut input_b = ReadSlice::new(diff_b)?;
+	let mut segments_b = Segments::new(DiffReader::new(&mut input_b));
+
+	let mut buf_a = vec![0; 1024 * 64];
+	let mut buf_b = vec![0; 1024 * 64];
+	let mut position = 0;
+	let mut a = segments_a.next_segment()?;
+	let mut b = segments_b.next_segment()?;
+	loop {
+		let (mut sa, mut sb) = match (a, b) {
+			(None, None) => return Ok(None),
+			(Some(sa), Some(sb)) => (sa, sb),
+			_ => return Ok(Some(position)),
+		};
+		let size = min(sa.size(), sb.size());
+		match (&mut sa, &mut sb) {
+			(Segment::Source { offset: oa, .. }, Segment::Source { offset: ob, .. }) => {
+				if oa != ob {
+					return Ok(Some(position));
+				}
+			}
+			(Segment::Literal(da), Segment::Literal(db)) => {
+				let mut left = size;
+				while left > 0 {
+					let chunk = min(left, buf_a.len() as u64) as usize;
+					da.read_exact(&mut buf_a[..chunk])?;
+					db.read_exact(&mut buf_b[..chunk])?;
+					if let Some(index) = buf_a[..chunk]
+						.iter()
+						.zip(&buf_b[..chunk])
+						.position(|(x, y)| x != y)
+					{
+						return Ok(Some(position + size - left + index as u64));
+					}
+					left -= chunk as u64;
+				}
+			}
+			_ => return Ok(Some(position)),
+		}
+		position += size;
+		a = advance(sa, size, &mut segments_a)?;
+		b = advance(sb, size, &mut segments_b)?;
+	}
+}
+
+/// Drops first `size` bytes of the segment, reads next one if it is exhausted
+///
+/// Literal segments are expected to be already read by `size` bytes
+fn advance<'b>(
+	segment: Segment<'b>,
+	size: u64,
+	segments: &mut Segments<'_, 'b>,
+) -> IOResult<Option<Segment<'b>>> {
+	if segment.size() == size {
+		return segments.next_segment();
+	}
+	Ok(Some(match segment {
+		Segment::Source { offset, size: s } => Segment::Source {
+			offset: offset + size,
+			size: s - size,
+		},
+		Segment::Literal(data) => Segment::Literal(data.take_from_current(data.size() - size)),
+	}))
+}
+
+/// Checks if two diffs produce the same output for any source
+///
+/// See `first_difference`
+pub fn diffs_equivalent<'a, T: 'a + Read + Seek, U: 'a + Read + Seek>(
+	diff_a: T,
+	diff_b: U,
+) -> IOResult<bool> {
+	Ok(first_difference(diff_a, diff_b)?.is_none())
+}
+
+#[cfg(test)]
+mod first_difference_tests {
+	use super::{create_diff, diffs_equivalent, first_difference, normalize_diff};
+	use diff_block::DiffBlock;
+	use diff_writer::DiffWriter;
+	use readslice::ReadSlice;
+	use std::io::Cursor;
+	use test_mod::TextFile;
+
+	fn write(blocks: Vec<DiffBlock<'static, u32>>) -> Cursor<Vec<u8>> {
+		let mut writer = DiffWriter::new(vec![]);
+		writer.write_blocks(blocks).unwrap();
+		Cursor::new(writer.finish().unwrap())
+	}
+
+	fn add(data: &[u8]) -> DiffBlock<'static, u32> {
+		DiffBlock::Add {
+			data: ReadSlice::from(data.to_vec()),
+		}
+	}
+
+	#[test]
+	fn works_test() {
+		let a = write(vec![
+			DiffBlock::Skip { size: 4 },
+			DiffBlock::Replace {
+				remove_size: 2,
+				data: ReadSlice::from(b"hello".to_vec()),
+			},
+			DiffBlock::Skip { size: 3 },
+		]);
+		let b = write(vec![
+			DiffBlock::Skip { size: 1 },
+			DiffBlock::Skip { size: 3 },
+			add(b"he"),
+			DiffBlock::Remove { size: 2 },
+			add(b"llo"),
+			DiffBlock::Skip { size: 3 },
+		]);
+		assert_eq!(first_difference(a.clone(), b).unwrap(), None);
+
+		let c = write(vec![
+			DiffBlock::Skip { size: 4 },
+			add(b"help"),
+			DiffBlock::Remove { size: 2 },
+			DiffBlock::Skip { size: 3 },
+		]);
+		assert_eq!(first_difference(a.clone(), c).unwrap(), Some(7));
+
+		let d = write(vec![
+			DiffBlock::Skip { size: 4 },
+			DiffBlock::Replace {
+				remove_size: 1,
+				data: ReadSlice::from(b"hello".to_vec()),
+			},
+			DiffBlock::Skip { size: 3 },
+		]);
+		assert_eq!(first_difference(a.clone(), d).unwrap(), Some(9));
+
+		let e = write(vec![DiffBlock::Skip { size: 4 }, add(b"hello")]);
+		assert_eq!(first_difference(a, e).unwrap(), Some(9));
+	}
+
+	#[test]
+	fn normalized_test() {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_c.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		let mut normalized = vec![];
+		normalize_diff(Cursor::new(diff.clone()), &mut normalized).unwrap();
+		assert!(diffs_equivalent(Cursor::new(diff.clone()), Cursor::new(normalized)).unwrap());
+
+		let mut other = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_d.txt"),
+			&mut other,
+		)
+		.unwrap();
+		assert!(!diffs_equivalent(Cursor::new(diff), Cursor::new(other)).unwrap());
+	}
 }
@@ -1,11 +1,14 @@
+//! Contains `DiffBlock`
+
 use bytes_serializer::{BytesSerializer, IntoBytesSerializer};
 use cmp::Cmp;
 use functions::{u16_to_u8_be_vec, u32_to_u8_be_vec};
-use readslice::ReadSlice;
-use std::io::{Cursor, Read};
+use readslice::{Local, ReadSlice, Sharing};
+use std::io::{Cursor, Read, Result as IOResult, Seek, SeekFrom};
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 
-#[derive(Clone, Debug)]
+/// Diff block without payload, only sizes are stored
+#[derive(Clone, Debug, PartialEq)]
 pub enum DiffBlockN<T: Add + AddAssign + Sub + SubAssign> {
 	Skip(T),
 	Add(T),
@@ -14,17 +17,109 @@ pub enum DiffBlockN<T: Add + AddAssign + Sub + SubAssign> {
 	ReplaceWithSameLength(T),
 }
 
+/// Block of the diff
+///
+/// See crate documentation for the binary representation of each block
 #[derive(Clone, Debug)]
-pub enum DiffBlock<'a, T: Add + AddAssign + Sub + SubAssign> {
+pub enum DiffBlock<'a, T: Add + AddAssign + Sub + SubAssign, K: Sharing = Local> {
+	/// Copies `size` bytes from the source
 	Skip { size: T },
-	Add { data: ReadSlice<'a> },
+	/// Inserts `data`
+	Add { data: ReadSlice<'a, K> },
+	/// Skips `size` bytes of the source without copying
 	Remove { size: T },
-	Replace { remove_size: T, data: ReadSlice<'a> },
-	ReplaceWithSameLength { data: ReadSlice<'a> },
+	/// Skips `remove_size` bytes of the source and inserts `data` instead
+	Replace {
+		remove_size: T,
+		data: ReadSlice<'a, K>,
+	},
+	/// Skips as many bytes of the source as `data` has and inserts `data` instead
+	ReplaceWithSameLength { data: ReadSlice<'a, K> },
 }
 
-impl<'a> DiffBlock<'a, u32> {
-	fn get_action_number<'b>(&self, other: &DiffBlock<'b, u32>) -> u8 {
+impl<'a, T: Add + AddAssign + Sub + SubAssign, K: Sharing> DiffBlock<'a, T, K> {
+	/// Returns payload of the block, `None` for blocks without payload
+	pub fn data(&self) -> Option<&ReadSlice<'a, K>> {
+		match self {
+			DiffBlock::Add { data }
+			| DiffBlock::Replace { data, .. }
+			| DiffBlock::ReplaceWithSameLength { data } => Some(data),
+			DiffBlock::Skip { .. } | DiffBlock::Remove { .. } => None,
+		}
+	}
+
+	/// Returns mutable payload of the block, `None` for blocks without payload
+	pub fn data_mut(&mut self) -> Option<&mut ReadSlice<'a, K>> {
+		match self {
+			DiffBlock::Add { data }
+			| DiffBlock::Replace { data, .. }
+			| DiffBlock::ReplaceWithSameLength { data } => Some(data),
+			DiffBlock::Skip { .. } | DiffBlock::Remove { .. } => None,
+		}
+	}
+
+	/// Reads whole payload regardless of its current position
+	///
+	/// Returns empty vector for blocks without payload
+	pub fn read_data(&self) -> IOResult<Vec<u8>> {
+		let mut out = vec![];
+		if let Some(data) = self.data() {
+			let mut data = data.clone();
+			data.seek(SeekFrom::Start(0))?;
+			data.read_to_end(&mut out)?;
+		}
+		Ok(out)
+	}
+}
+
+impl<'a, K: Sharing> DiffBlock<'a, u32, K> {
+	/// Returns number of source bytes the block consumes
+	pub fn source_size(&self) -> u64 {
+		match self {
+			DiffBlock::Skip { size } | DiffBlock::Remove { size } => u64::from(*size),
+			DiffBlock::Add { .. } => 0,
+			DiffBlock::Replace { remove_size, .. } => u64::from(*remove_size),
+			DiffBlock::ReplaceWithSameLength { data } => data.size(),
+		}
+	}
+
+	/// Returns the simplest block with the same effect
+	///
+	/// Replace without removal becomes add, replace without data becomes remove,
+	/// replace of equal sizes becomes replace with same length
+	pub fn normalized(self) -> Self {
+		match self {
+			DiffBlock::Replace {
+				remove_size: 0,
+				data,
+			} => DiffBlock::Add { data },
+			DiffBlock::Replace { remove_size, data } if data.size() == 0 => {
+				DiffBlock::Remove { size: remove_size }
+			}
+			DiffBlock::Replace { remove_size, data } if u64::from(remove_size) == data.size() => {
+				DiffBlock::ReplaceWithSameLength { data }
+			}
+			_ => self,
+		}
+	}
+
+	/// Returns `true` if the block neither consumes nor produces any bytes
+	pub fn is_empty(&self) -> bool {
+		self.source_size() == 0 && self.target_size() == 0
+	}
+
+	/// Returns number of bytes the block produces in the target
+	pub fn target_size(&self) -> u64 {
+		match self {
+			DiffBlock::Skip { size } => u64::from(*size),
+			DiffBlock::Remove { .. } => 0,
+			DiffBlock::Add { data }
+			| DiffBlock::Replace { data, .. }
+			| DiffBlock::ReplaceWithSameLength { data } => data.size(),
+		}
+	}
+
+	fn get_action_number<'b>(&self, other: &DiffBlock<'b, u32, K>) -> u8 {
 		let mut action = 0;
 		match &self {
 			DiffBlock::Skip { .. } => action += 10,
@@ -43,13 +138,16 @@ impl<'a> DiffBlock<'a, u32> {
 		action
 	}
 
+	/// Applies `other` block of the next diff to this block, used by combine functions
+	///
+	/// Returns combined block and leftovers of this and other blocks
 	pub fn diff(
 		self,
-		other: DiffBlock<'a, u32>,
+		other: DiffBlock<'a, u32, K>,
 	) -> (
-		Option<DiffBlock<'a, u32>>,
-		Option<DiffBlock<'a, u32>>,
-		Option<DiffBlock<'a, u32>>,
+		Option<DiffBlock<'a, u32, K>>,
+		Option<DiffBlock<'a, u32, K>>,
+		Option<DiffBlock<'a, u32, K>>,
 	) {
 		let action = self.get_action_number(&other);
 		match action {
@@ -335,10 +433,10 @@ impl<'a> DiffBlock<'a, u32> {
 	}
 }
 
-impl<'a> Add for DiffBlock<'a, u32> {
-	type Output = (DiffBlock<'a, u32>, Option<DiffBlock<'a, u32>>);
+impl<'a, K: Sharing> Add for DiffBlock<'a, u32, K> {
+	type Output = (DiffBlock<'a, u32, K>, Option<DiffBlock<'a, u32, K>>);
 
-	fn add(self, other: DiffBlock<'a, u32>) -> Self::Output {
+	fn add(self, other: DiffBlock<'a, u32, K>) -> Self::Output {
 		let action = self.get_action_number(&other);
 		match action {
 			// skip
@@ -365,7 +463,7 @@ impl<'a> Add for DiffBlock<'a, u32> {
 			22 => match (self, other) {
 				(DiffBlock::Add { mut data }, DiffBlock::Add { data: mut datab }) => (
 					DiffBlock::Add {
-						data: ReadSlice::chain(&data, datab),
+						data: data.append(&datab),
 					},
 					None,
 				),
@@ -448,7 +546,7 @@ impl<'a> Add for DiffBlock<'a, u32> {
 					if remove_size == size + sizeb {
 						return (
 							DiffBlock::ReplaceWithSameLength {
-								data: ReadSlice::chain(&data, datab),
+								data: data.append(&datab),
 							},
 							None,
 						);
@@ -456,7 +554,7 @@ impl<'a> Add for DiffBlock<'a, u32> {
 					(
 						DiffBlock::Replace {
 							remove_size,
-							data: ReadSlice::chain(&data, datab),
+							data: data.append(&datab),
 						},
 						None,
 					)
@@ -483,7 +581,7 @@ impl<'a> Add for DiffBlock<'a, u32> {
 					(
 						DiffBlock::Replace {
 							remove_size: size,
-							data: ReadSlice::chain(&data, datab),
+							data: data.append(&datab),
 						},
 						None,
 					)
@@ -501,8 +599,8 @@ impl<'a> Add for DiffBlock<'a, u32> {
 	}
 }
 
-impl<'a> IntoBytesSerializer for DiffBlock<'a, u32> {
-	type Item = DiffBlock<'a, u32>;
+impl<'a, K: Sharing> IntoBytesSerializer for DiffBlock<'a, u32, K> {
+	type Item = DiffBlock<'a, u32, K>;
 
 	fn into_bytes(self) -> BytesSerializer<Self::Item> {
 		BytesSerializer::new(
@@ -597,7 +695,7 @@ mod diff_block_tests {
 	fn diff_block_diff_add_greater_skip_test() {
 		let data = Cursor::new([1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
 		let da = DiffBlock::Add {
-			data: ReadSlice::new(data),
+			data: ReadSlice::new(data).unwrap(),
 		};
 		let db: DiffBlock<u32> = DiffBlock::Skip { size: 4u32 };
 		let op = da.diff(db);
@@ -619,7 +717,7 @@ mod diff_block_tests {
 	fn diffblock_read_test() {
 		let data = Cursor::new([1, 2, 3, 4, 5, 6]);
 		let block = DiffBlock::Add {
-			data: ReadSlice::new(data),
+			data: ReadSlice::new(data).unwrap(),
 		};
 		let mut buf = vec![0; 2 + 4 + 6];
 		block.into_bytes().read_exact(&mut buf).unwrap();
@@ -0,0 +1,264 @@
+//! Contains `DiffIndex`
+//!
+//! Index maps target offsets to blocks of the diff, so any part of the target can be
+//! reconstructed without reading the diff from the start (see `PatchedFile`).
+
+use decoder::Decoder;
+use diff_block::{DiffBlock, DiffBlockN};
+use functions::{u64_to_u8_be_vec, vec_to_u64_be};
+use std::io::{Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom};
+
+/// Block of the diff producing target bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+	/// Offset of the first produced byte in the target
+	pub target_offset: u64,
+	/// Offset of the block in the diff
+	pub diff_offset: u64,
+	/// Offset of the source at the start of the block
+	pub source_offset: u64,
+}
+
+/// Index of the diff blocks by target offset
+///
+/// Only blocks producing target bytes are indexed, so entries are sorted by `target_offset`
+/// without duplicates
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiffIndex {
+	entries: Vec<IndexEntry>,
+	target_size: u64,
+	source_size: u64,
+	diff_size: u64,
+}
+
+/// Reads block header, returns `(action, source_size, target_size, header_size)`
+///
+/// Returns `None` at the end of the diff
+fn read_header<T: Read>(input: &mut T) -> IOResult<Option<(u16, u64, u64, u64)>> {
+	let mut decoder = Decoder::new();
+	let block = match decoder.read_block(input)? {
+		Some(x) => x,
+		None if decoder.is_complete() => return Ok(None),
+		None => {
+			return Err(Error::new(
+				ErrorKind::UnexpectedEof,
+				"Diff is truncated in block header",
+			))
+		}
+	};
+	let header = match block {
+		DiffBlockN::Skip(size) => (0, size, size),
+		DiffBlockN::Add(size) => (1, 0, size),
+		DiffBlockN::Remove(size) => (2, size, 0),
+		DiffBlockN::Replace(remove, add) => (3, remove, add),
+		DiffBlockN::ReplaceWithSameLength(size) => (4, size, size),
+	};
+	Ok(Some((
+		header.0,
+		u64::from(header.1),
+		u64::from(header.2),
+		decoder.offset(),
+	)))
+}
+
+impl DiffIndex {
+	/// Creates empty index
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builds index of the diff
+	///
+	/// Only block headers are read, payloads are seeked over. Fails with `ErrorKind::UnexpectedEof`
+	/// if the diff is truncated
+	pub fn from_diff<T: Read + Seek>(diff: &mut T) -> IOResult<Self> {
+		let start = diff.stream_position()?;
+		let end = diff.seek(SeekFrom::End(0))?;
+		diff.seek(SeekFrom::Start(start))?;
+		let mut index = Self::new();
+		while let Some((action, source_size, target_size, header_size)) = read_header(diff)? {
+			let data_size = match action {
+				0 | 2 => 0,
+				_ => target_size,
+			};
+			if diff.seek(SeekFrom::Current(data_size as i64))? > end {
+				return Err(Error::new(
+					ErrorKind::UnexpectedEof,
+					"Diff is truncated in block data",
+				));
+			}
+			index.push(source_size, target_size, header_size + data_size);
+		}
+		Ok(index)
+	}
+
+	/// Adds next block of the diff
+	pub fn push_block(&mut self, block: &DiffBlock<u32>) {
+		let header_size = match block {
+			DiffBlock::Replace { .. } => 10,
+			_ => 6,
+		};
+		let data_size = match block {
+			DiffBlock::Skip { .. } | DiffBlock::Remove { .. } => 0,
+			_ => block.target_size(),
+		};
+		self.push(
+			block.source_size(),
+			block.target_size(),
+			header_size + data_size,
+		);
+	}
+
+	fn push(&mut self, source_size: u64, target_size: u64, block_size: u64) {
+		if target_size > 0 {
+			self.entries.push(IndexEntry {
+				target_offset: self.target_size,
+				diff_offset: self.diff_size,
+				source_offset: self.source_size,
+			});
+		}
+		self.target_size += target_size;
+		self.source_size += source_size;
+		self.diff_size += block_size;
+	}
+
+	/// Returns indexed blocks
+	pub fn entries(&self) -> &[IndexEntry] {
+		&self.entries
+	}
+
+	/// Returns size of the target
+	pub fn target_size(&self) -> u64 {
+		self.target_size
+	}
+
+	/// Returns size of the source part covered by the diff
+	pub fn source_size(&self) -> u64 {
+		self.source_size
+	}
+
+	/// Returns size of the diff
+	pub fn diff_size(&self) -> u64 {
+		self.diff_size
+	}
+
+	/// Returns position of the entry containing target byte at `offset`
+	pub fn find(&self, offset: u64) -> Option<usize> {
+		if offset >= self.target_size {
+			return None;
+		}
+		Some(
+			self.entries
+				.partition_point(|x| x.target_offset <= offset)
+				.saturating_sub(1),
+		)
+	}
+
+	/// Returns target offset after the last byte of the entry
+	pub fn entry_end(&self, position: usize) -> u64 {
+		match self.entries.get(position + 1) {
+			Some(next) => next.target_offset,
+			None => self.target_size,
+		}
+	}
+
+	/// Serializes index to binary representation
+	///
+	/// ```bash
+	/// target_size : 8
+	/// source_size : 8
+	/// diff_size : 8
+	/// entries_count : 8
+	/// entry_{n}:
+	///   target_offset : 8
+	///   diff_offset : 8
+	///   source_offset : 8
+	/// ```
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out = vec![];
+		out.extend_from_slice(&u64_to_u8_be_vec(self.target_size));
+		out.extend_from_slice(&u64_to_u8_be_vec(self.source_size));
+		out.extend_from_slice(&u64_to_u8_be_vec(self.diff_size));
+		out.extend_from_slice(&u64_to_u8_be_vec(self.entries.len() as u64));
+		for entry in &self.entries {
+			out.extend_from_slice(&u64_to_u8_be_vec(entry.target_offset));
+			out.extend_from_slice(&u64_to_u8_be_vec(entry.diff_offset));
+			out.extend_from_slice(&u64_to_u8_be_vec(entry.source_offset));
+		}
+		out
+	}
+
+	/// Parses index from binary representation produced by `to_bytes`
+	pub fn from_bytes(input: &[u8]) -> Result<Self, String> {
+		if input.len() < 32 {
+			return Err("Unexpected end of index data".to_string());
+		}
+		let field = |n: usize| vec_to_u64_be(&input[n * 8..n * 8 + 8]);
+		let count = field(3);
+		if Some(input.len() as u64 - 32) != count.checked_mul(24) {
+			return Err("Index data length doesn't match entries count".to_string());
+		}
+		let mut entries: Vec<IndexEntry> = Vec::with_capacity(count as usize);
+		for n in 0..count as usize {
+			let entry = IndexEntry {
+				target_offset: field(4 + n * 3),
+				diff_offset: field(5 + n * 3),
+				source_offset: field(6 + n * 3),
+			};
+			if let Some(last) = entries.last() {
+				if last.target_offset >= entry.target_offset {
+					return Err(format!("Index entry {} is out of order", n));
+				}
+			}
+			entries.push(entry);
+		}
+		Ok(Self {
+			entries,
+			target_size: field(0),
+			source_size: field(1),
+			diff_size: field(2),
+		})
+	}
+}
+
+#[cfg(test)]
+mod diff_index_tests {
+	use super::DiffIndex;
+	use diff::create_diff_with_index;
+	use std::io::{Cursor, ErrorKind};
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let mut diff = Cursor::new(vec![]);
+		let index = create_diff_with_index(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		assert!(index.entries().len() > 1);
+		assert_eq!(index.diff_size(), diff.get_ref().len() as u64);
+
+		diff.set_position(0);
+		assert_eq!(DiffIndex::from_diff(&mut diff).unwrap(), index);
+		assert_eq!(DiffIndex::from_bytes(&index.to_bytes()).unwrap(), index);
+		assert!(DiffIndex::from_bytes(&index.to_bytes()[1..]).is_err());
+		for size in [3, diff.get_ref().len() - 1].iter() {
+			let mut truncated = Cursor::new(&diff.get_ref()[..*size]);
+			let error = DiffIndex::from_diff(&mut truncated).unwrap_err();
+			assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+		}
+		let mut truncated = Cursor::new(vec![0, 1, 0, 0, 0, 5, b'a']);
+		let error = DiffIndex::from_diff(&mut truncated).unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+		assert_eq!(index.find(0), Some(0));
+		let last = index.entries().len() - 1;
+		assert_eq!(index.find(index.target_size() - 1), Some(last));
+		assert_eq!(index.find(index.target_size()), None);
+		let second = index.entries()[1].target_offset;
+		assert_eq!(index.find(second - 1), Some(0));
+		assert_eq!(index.find(second), Some(1));
+	}
+}
@@ -1,9 +1,28 @@
 use diff_block::{DiffBlock, DiffBlockN};
 use difference::{Changeset, Difference};
+use hasher::{BlockHasher, Digest, Sha256Hasher};
 use indexes::WithIndexes;
-use lines_with_hash_iterator::LinesWithHashIterator;
+use lines_with_hash_iterator::{hash_lines_parallel, LinesWithHashIterator};
 use readslice::ReadSlice;
-use std::io::SeekFrom;
+use signature::Signature;
+use std::io::{Result as IOResult, SeekFrom};
+
+/// Joins hex representations of line digests with newlines
+fn hex_lines(lines: &[(String, u64, u64, Digest)]) -> String {
+	const HEX: &[u8; 16] = b"0123456789abcdef";
+	let size = lines.iter().map(|x| x.3.as_bytes().len() * 2 + 1).sum();
+	let mut out = String::with_capacity(size);
+	for (index, line) in lines.iter().enumerate() {
+		if index > 0 {
+			out.push('\n');
+		}
+		for byte in line.3.as_bytes() {
+			out.push(HEX[(byte >> 4) as usize] as char);
+			out.push(HEX[(byte & 0xf) as usize] as char);
+		}
+	}
+	out
+}
 
 pub struct DiffIterator<T: WithIndexes> {
 	file: T,
@@ -14,49 +33,96 @@ pub struct DiffIterator<T: WithIndexes> {
 
 impl<T: WithIndexes> DiffIterator<T> {
 	pub fn new<U: WithIndexes>(file_a: U, file_b: T) -> Result<Self, String> {
-		let (_file_a, ind_a) = {
-			let mut it = LinesWithHashIterator::new(file_a)?;
-			let ind: Vec<_> = it.by_ref().collect();
-			let r = it.get_read();
-			(r, ind.clone())
-		};
+		Self::with_hasher(file_a, file_b, Sha256Hasher)
+	}
+
+	pub fn with_hasher<U: WithIndexes, H: BlockHasher>(
+		file_a: U,
+		file_b: T,
+		hasher: H,
+	) -> Result<Self, String> {
+		let ind_a =
+			LinesWithHashIterator::with_hasher(file_a, &hasher)?.collect::<Result<Vec<_>, _>>()?;
+		Self::new_from_lines(&ind_a, file_b, hasher)
+	}
+
+	pub fn new_from_signature<H: BlockHasher>(
+		signature: &Signature,
+		file_b: T,
+		hasher: H,
+	) -> Result<Self, String> {
+		if signature.hasher() != hasher.name() {
+			return Err(format!(
+				"Signature was computed with \"{}\" hasher, got \"{}\"",
+				signature.hasher(),
+				hasher.name()
+			));
+		}
+		Self::new_from_lines(signature.lines(), file_b, hasher)
+	}
+
+	pub fn new_parallel<U, F, G, H>(
+		open_a: F,
+		open_b: G,
+		hasher: H,
+		threads: usize,
+	) -> Result<Self, String>
+	where
+		U: WithIndexes,
+		F: Fn() -> IOResult<U> + Sync,
+		G: Fn() -> IOResult<T> + Sync,
+		H: BlockHasher + Sync,
+	{
+		let ind_a = hash_lines_parallel(open_a, &hasher, threads)?;
+		let ind_b = hash_lines_parallel(&open_b, &hasher, threads)?;
+		let file_b = open_b().map_err(|e| e.to_string())?;
+		Ok(Self::new_from_hashed_lines(&ind_a, &ind_b, file_b))
+	}
+
+	fn new_from_lines<H: BlockHasher>(
+		ind_a: &[(String, u64, u64, Digest)],
+		file_b: T,
+		hasher: H,
+	) -> Result<Self, String> {
 		let (file_b, ind_b) = {
-			let mut it = LinesWithHashIterator::new(file_b)?;
-			let ind: Vec<_> = it.by_ref().collect();
+			let mut it = LinesWithHashIterator::with_hasher(file_b, hasher)?;
+			let ind = it.by_ref().collect::<Result<Vec<_>, _>>()?;
 			let r = it.get_read();
-			(r, ind.clone())
+			(r, ind)
 		};
 
-		let ind_a_h = (&ind_a)
-			.into_iter()
-			.by_ref()
-			.map(|x| x.3.clone())
-			.collect::<Vec<String>>()
-			.join("\n");
-		let ind_b_h = (&ind_b)
-			.into_iter()
-			.by_ref()
-			.map(|x| x.3.clone())
-			.collect::<Vec<String>>()
-			.join("\n");
+		Ok(Self::new_from_hashed_lines(ind_a, &ind_b, file_b))
+	}
+
+	/// Diffs line digests of both files
+	///
+	/// `difference` compares text, so digests are written as hex lines of a single string.
+	/// The conversion stays on purpose for this backend, but no string is allocated per line
+	fn new_from_hashed_lines(
+		ind_a: &[(String, u64, u64, Digest)],
+		ind_b: &[(String, u64, u64, Digest)],
+		file_b: T,
+	) -> Self {
+		let ind_a_h = hex_lines(ind_a);
+		let ind_b_h = hex_lines(ind_b);
 
 		let diffs = {
 			let changeset = Changeset::new(&ind_a_h, &ind_b_h, "\n");
 			changeset.diffs
 		};
 
-		let diffs = Self::process_diff(&diffs);
-		let diffs = Self::process_diff_2(&diffs, &ind_a, &ind_b);
+		let diffs = Self::process_changeset(&diffs);
+		let diffs = Self::process_diff(&diffs, ind_a, ind_b);
 
-		Ok(Self {
+		Self {
 			file: file_b,
 			diff: diffs,
 			pos: 0,
 			file_pos: 0,
-		})
+		}
 	}
 
-	fn process_diff(diffs: &[Difference]) -> Vec<DiffBlockN<usize>> {
+	fn process_changeset(diffs: &[Difference]) -> Vec<DiffBlockN<usize>> {
 		let mut o: Vec<DiffBlockN<usize>> = vec![DiffBlockN::Skip(0)];
 
 		for d in diffs {
@@ -90,10 +156,10 @@ impl<T: WithIndexes> DiffIterator<T> {
 		o
 	}
 
-	fn process_diff_2(
+	fn process_diff(
 		diffs: &[DiffBlockN<usize>],
-		indexes_a: &[(String, u64, u64, String)],
-		indexes_b: &[(String, u64, u64, String)],
+		indexes_a: &[(String, u64, u64, Digest)],
+		indexes_b: &[(String, u64, u64, Digest)],
 	) -> Vec<DiffBlockN<u32>> {
 		let mut o: Vec<DiffBlockN<u32>> = vec![];
 		let mut i_a = indexes_a.into_iter().map(|x| x.2 as u32);
@@ -142,6 +208,25 @@ impl<T: WithIndexes> DiffIterator<T> {
 		o
 	}
 
+	pub fn next_size(&mut self) -> Option<u64> {
+		if self.pos >= self.diff.len() {
+			return None;
+		};
+
+		let item = &self.diff[self.pos];
+		self.pos += 1;
+
+		match item {
+			DiffBlockN::Skip(_size) => Some(6),
+			DiffBlockN::Add(size) => Some(6 + (u64::from(*size))),
+			DiffBlockN::Remove(_size) => Some(6),
+			DiffBlockN::Replace(_remove, add) => Some(10 + (u64::from(*add))),
+			DiffBlockN::ReplaceWithSameLength(size) => Some(6 + (u64::from(*size))),
+		}
+	}
+}
+
+impl<T: WithIndexes> DiffIterator<T> {
 	pub fn next_ref(&mut self) -> Option<Result<DiffBlock<u32>, String>> {
 		if self.pos >= self.diff.len() {
 			return None;
@@ -160,8 +245,10 @@ impl<T: WithIndexes> DiffIterator<T> {
 				if res.is_err() {
 					return Some(Err("Error while seeking file".to_string()));
 				};
-				let slice: ReadSlice =
-					ReadSlice::take_from_current(&ReadSlice::new(&mut self.file), u64::from(*size));
+				let slice = match ReadSlice::new(&mut self.file) {
+					Ok(x) => x.take(u64::from(*size)),
+					Err(e) => return Some(Err(e.to_string())),
+				};
 				self.file_pos += u64::from(*size);
 				Some(Ok(DiffBlock::Add { data: slice }))
 			}
@@ -171,8 +258,10 @@ impl<T: WithIndexes> DiffIterator<T> {
 				if res.is_err() {
 					return Some(Err("Error while seeking file".to_string()));
 				};
-				let slice =
-					ReadSlice::take_from_current(&ReadSlice::new(&mut self.file), u64::from(*add));
+				let slice = match ReadSlice::new(&mut self.file) {
+					Ok(x) => x.take(u64::from(*add)),
+					Err(e) => return Some(Err(e.to_string())),
+				};
 				self.file_pos += u64::from(*add);
 				Some(Ok(DiffBlock::Replace {
 					remove_size: *remove,
@@ -184,35 +273,21 @@ impl<T: WithIndexes> DiffIterator<T> {
 				if res.is_err() {
 					return Some(Err("Error while seeking file".to_string()));
 				};
-				let slice =
-					ReadSlice::take_from_current(&ReadSlice::new(&mut self.file), u64::from(*size));
+				let slice = match ReadSlice::new(&mut self.file) {
+					Ok(x) => x.take(u64::from(*size)),
+					Err(e) => return Some(Err(e.to_string())),
+				};
 				self.file_pos += u64::from(*size);
 				Some(Ok(DiffBlock::ReplaceWithSameLength { data: slice }))
 			}
 		}
 	}
-
-	pub fn next_size(&mut self) -> Option<u64> {
-		if self.pos >= self.diff.len() {
-			return None;
-		};
-
-		let item = &self.diff[self.pos];
-		self.pos += 1;
-
-		match item {
-			DiffBlockN::Skip(_size) => Some(6),
-			DiffBlockN::Add(size) => Some(6 + (u64::from(*size))),
-			DiffBlockN::Remove(_size) => Some(6),
-			DiffBlockN::Replace(_remove, add) => Some(10 + (u64::from(*add))),
-			DiffBlockN::ReplaceWithSameLength(size) => Some(6 + (u64::from(*size))),
-		}
-	}
 }
 
 #[cfg(test)]
 mod diff_iterator_tests {
-	use super::DiffIterator;
+	use super::{hex_lines, DiffIterator};
+	use hasher::Digest;
 	use test_mod::TextFile;
 
 	#[test]
@@ -245,4 +320,19 @@ mod diff_iterator_tests {
 		}
 		assert_eq!(i, 5);
 	}
+
+	#[test]
+	fn hex_lines_test() {
+		let lines = vec![
+			(String::new(), 0, 1, Digest::new(&[0x00, 0xaf])),
+			(String::new(), 1, 2, Digest::new(&[0x1f, 0xf0, 0x09])),
+		];
+		let expected = lines
+			.iter()
+			.map(|x| x.3.to_hex())
+			.collect::<Vec<String>>()
+			.join("\n");
+		assert_eq!(hex_lines(&lines), expected);
+		assert_eq!(hex_lines(&[]), "");
+	}
 }
@@ -1,102 +1,134 @@
-use diff_block::DiffBlock;
-use functions::{read_n, vec_shift, vec_to_u32_be};
-use readslice::ReadSlice;
+//! Contains `DiffReader`
+
+use decoder::Decoder;
+use diff_block::{DiffBlock, DiffBlockN};
+use functions::vec_shift;
+use readslice::{Local, ReadSlice, Sharing};
 use std::io::{Error, ErrorKind, Result as IOResult, Seek, SeekFrom};
 
-enum Either<'a, 'b: 'a> {
-	Input(&'a mut ReadSlice<'b>),
-	Vector(&'a mut Vec<DiffBlock<'b, u32>>),
+enum Either<'a, 'b: 'a, K: Sharing> {
+	Input(&'a mut ReadSlice<'b, K>),
+	Vector(&'a mut Vec<DiffBlock<'b, u32, K>>),
 }
 
-pub struct DiffReader<'a, 'b: 'a> {
-	input: Either<'a, 'b>,
-	buffer: [u8; 4],
+/// Streaming reader of diff blocks
+///
+/// Blocks payloads are not copied, they are `ReadSlice`s pointing into the input.
+/// Reader can be used either with `next_block` or as an `Iterator`. Diff truncated in the middle
+/// of the block fails with `ErrorKind::UnexpectedEof`
+///
+/// ```
+/// use bin_diff::diff_reader::DiffReader;
+/// use bin_diff::readslice::ReadSlice;
+///
+/// let diff = vec![0, 0, 0, 0, 0, 3, 0, 1, 0, 0, 0, 2, b'h', b'i'];
+/// let mut input = ReadSlice::from(diff);
+/// let blocks = DiffReader::new(&mut input).collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(blocks.len(), 2);
+/// assert_eq!(blocks[1].read_data().unwrap(), b"hi");
+/// ```
+pub struct DiffReader<'a, 'b: 'a, K: Sharing = Local> {
+	input: Either<'a, 'b, K>,
 }
 
-impl<'a, 'b: 'a> DiffReader<'a, 'b> {
-	pub fn new(input: &'a mut ReadSlice<'b>) -> Self {
+impl<'a, 'b: 'a, K: Sharing> DiffReader<'a, 'b, K> {
+	/// Creates reader of the binary diff, starting from current position of the input
+	pub fn new(input: &'a mut ReadSlice<'b, K>) -> Self {
 		Self {
 			input: Either::Input(input),
-			buffer: [0; 4],
 		}
 	}
 
-	pub fn new_from_vector(input: &'a mut Vec<DiffBlock<'b, u32>>) -> Self {
+	/// Creates reader yielding already parsed blocks
+	pub fn new_from_vector(input: &'a mut Vec<DiffBlock<'b, u32, K>>) -> Self {
 		Self {
 			input: Either::Vector(input),
-			buffer: [0; 4],
 		}
 	}
 
-	pub fn next(&mut self) -> IOResult<Option<DiffBlock<'b, u32>>> {
+	/// Reads next block, returns `None` at the end of the diff
+	pub fn next_block(&mut self) -> IOResult<Option<DiffBlock<'b, u32, K>>> {
 		match &mut self.input {
 			Either::Input(ref mut input) => {
-				let read_size = read_n(input, &mut self.buffer, 2);
-				match read_size {
-					Err(e) => match e.kind() {
-						ErrorKind::UnexpectedEof => {
-							return Ok(None);
-						}
-						_ => {
-							return Err(e);
-						}
-					},
-					Ok(x) => {
-						if x == 0 {
-							return Ok(None);
-						}
+				let mut decoder = Decoder::new();
+				let block = match decoder.read_block(input)? {
+					Some(x) => x,
+					None if decoder.is_complete() => return Ok(None),
+					None => {
+						return Err(Error::new(
+							ErrorKind::UnexpectedEof,
+							"Diff is truncated in block header",
+						))
 					}
 				};
-				let action = vec_to_u32_be(&self.buffer[0..2]);
-				match action {
-					0 => {
-						read_n(input, &mut self.buffer, 4)?;
-						let size = vec_to_u32_be(&self.buffer);
-						Ok(Some(DiffBlock::Skip { size }))
-					}
-					1 => {
-						read_n(input, &mut self.buffer, 4)?;
-						let size = vec_to_u32_be(&self.buffer);
-						let mut data = ReadSlice::take_from_current(input, u64::from(size));
-						ReadSlice::seek(input, SeekFrom::Current(i64::from(size)))?;
-						Ok(Some(DiffBlock::Add { data }))
-					}
-					2 => {
-						read_n(input, &mut self.buffer, 4)?;
-						let size = vec_to_u32_be(&self.buffer);
-						Ok(Some(DiffBlock::Remove { size }))
-					}
-					3 => {
-						read_n(input, &mut self.buffer, 4)?;
-						let remove_size = vec_to_u32_be(&self.buffer);
-						read_n(input, &mut self.buffer, 4)?;
-						let size = vec_to_u32_be(&self.buffer);
-						let mut data = ReadSlice::take_from_current(input, u64::from(size));
-						ReadSlice::seek(input, SeekFrom::Current(i64::from(size)))?;
-						Ok(Some(DiffBlock::Replace { remove_size, data }))
-					}
-					4 => {
-						read_n(input, &mut self.buffer, 4)?;
-						let size = vec_to_u32_be(&self.buffer);
-						let mut data = ReadSlice::take_from_current(input, u64::from(size));
-						ReadSlice::seek(input, SeekFrom::Current(i64::from(size)))?;
-						Ok(Some(DiffBlock::ReplaceWithSameLength { data }))
-					}
-					_ => Err(Error::new(ErrorKind::InvalidData, "Unknown Action")),
+				let size = match block {
+					DiffBlockN::Skip(size) => return Ok(Some(DiffBlock::Skip { size })),
+					DiffBlockN::Remove(size) => return Ok(Some(DiffBlock::Remove { size })),
+					DiffBlockN::Add(size)
+					| DiffBlockN::Replace(_, size)
+					| DiffBlockN::ReplaceWithSameLength(size) => u64::from(size),
+				};
+				let data = ReadSlice::take_from_current(input, size);
+				if data.size() < size {
+					return Err(Error::new(
+						ErrorKind::UnexpectedEof,
+						"Diff is truncated in block data",
+					));
 				}
+				ReadSlice::seek(input, SeekFrom::Current(decoder.skip(data.size()) as i64))?;
+				Ok(Some(match block {
+					DiffBlockN::Replace(remove_size, _) => DiffBlock::Replace { remove_size, data },
+					DiffBlockN::ReplaceWithSameLength(_) => {
+						DiffBlock::ReplaceWithSameLength { data }
+					}
+					_ => DiffBlock::Add { data },
+				}))
 			}
 			Either::Vector(ref mut input) => Ok(vec_shift(input)),
 		}
 	}
 
-	#[allow(dead_code)]
-	pub fn consume(&mut self) -> IOResult<Vec<DiffBlock<'b, u32>>> {
+	/// Reads all remaining blocks
+	pub fn consume(&mut self) -> IOResult<Vec<DiffBlock<'b, u32, K>>> {
 		let mut out = vec![];
 
-		while let Some(block) = self.next()? {
+		while let Some(block) = self.next_block()? {
 			out.push(block);
 		}
 
 		Ok(out)
 	}
 }
+
+impl<'a, 'b: 'a, K: Sharing> Iterator for DiffReader<'a, 'b, K> {
+	type Item = IOResult<DiffBlock<'b, u32, K>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.next_block().transpose()
+	}
+}
+
+#[cfg(test)]
+mod diff_reader_tests {
+	use super::DiffReader;
+	use diff::normalize_diff;
+	use readslice::ReadSlice;
+	use std::io::{Cursor, ErrorKind};
+
+	#[test]
+	fn truncated_test() {
+		let diff = vec![0, 0, 0, 0, 0, 3, 0, 1, 0, 0, 0, 5, b'a'];
+		let mut input = ReadSlice::from(diff.clone());
+		let mut reader = DiffReader::new(&mut input);
+		assert!(reader.next_block().unwrap().is_some());
+		let error = reader.next_block().unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+		let mut input = ReadSlice::from(diff[..4].to_vec());
+		let error = DiffReader::new(&mut input).next_block().unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+		let error = normalize_diff(&mut Cursor::new(diff), &mut vec![]).unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+	}
+}
@@ -0,0 +1,306 @@
+//! Contains `DiffWriter`
+
+use bytes_serializer::IntoBytesSerializer;
+use diff_block::DiffBlock;
+use readslice::ReadSlice;
+use std::cmp::min;
+use std::io::{copy, Error, ErrorKind, Result as IOResult, Seek, SeekFrom, Write};
+
+/// Serializes diff blocks into binary diff
+///
+/// Counterpart of `DiffReader`, blocks read by it can be written back unchanged or transformed.
+///
+/// Diff can also be built from edits addressed by source offsets with `insert`, `remove` and `replace`.
+/// Edits must come in source order and must not overlap, gaps between them are skipped.
+/// Adjacent edits are merged by `DiffBlock` `Add` rules, blocks longer than `u32::MAX` are split.
+/// Existing blocks can be merged the same way with `append`.
+/// Use `finish_with_source_size` to keep the source tail after the last edit
+///
+/// ```
+/// use bin_diff::diff_block::DiffBlock;
+/// use bin_diff::diff_writer::DiffWriter;
+/// use bin_diff::readslice::ReadSlice;
+///
+/// let mut writer = DiffWriter::new(vec![]);
+/// writer.write_block(DiffBlock::Skip { size: 3 }).unwrap();
+/// let data = ReadSlice::from(b"hi".to_vec());
+/// writer.write_block(DiffBlock::Add { data }).unwrap();
+/// let diff = writer.finish().unwrap();
+/// assert_eq!(diff, [0, 0, 0, 0, 0, 3, 0, 1, 0, 0, 0, 2, b'h', b'i']);
+/// ```
+pub struct DiffWriter<'a, W: Write> {
+	output: W,
+	position: u64,
+	pending: Option<DiffBlock<'a, u32>>,
+}
+
+impl<'a, W: Write> DiffWriter<'a, W> {
+	/// Creates writer appending blocks to the output
+	pub fn new(output: W) -> Self {
+		Self {
+			output,
+			position: 0,
+			pending: None,
+		}
+	}
+
+	/// Returns source offset reached by the written blocks and edits
+	pub fn source_position(&self) -> u64 {
+		self.position
+	}
+
+	/// Writes block, its payload is written whole regardless of its current position
+	///
+	/// Fails if the payload is larger than the format allows
+	pub fn write_block(&mut self, block: DiffBlock<u32>) -> IOResult<()> {
+		self.flush_pending()?;
+		self.position += block.source_size();
+		self.write_raw(block)
+	}
+
+	/// Writes all blocks of the iterator
+	pub fn write_blocks<'b, I: IntoIterator<Item = DiffBlock<'b, u32>>>(
+		&mut self,
+		blocks: I,
+	) -> IOResult<()> {
+		for block in blocks {
+			self.write_block(block)?;
+		}
+		Ok(())
+	}
+
+	/// Appends block merging it with the previous one by `DiffBlock` `Add` rules
+	///
+	/// Blocks which change nothing are dropped
+	pub fn append(&mut self, block: DiffBlock<'a, u32>) -> IOResult<()> {
+		if block.is_empty() {
+			return Ok(());
+		}
+		self.position += block.source_size();
+		self.push(block.normalized())
+	}
+
+	/// Inserts `data` before source byte at `offset`
+	pub fn insert(&mut self, offset: u64, data: ReadSlice<'a>) -> IOResult<()> {
+		self.seek_source(offset)?;
+		self.push_data(data)
+	}
+
+	/// Removes `size` source bytes starting at `offset`
+	pub fn remove(&mut self, offset: u64, size: u64) -> IOResult<()> {
+		self.seek_source(offset)?;
+		self.push_sized(size, |size| DiffBlock::Remove { size })?;
+		self.position += size;
+		Ok(())
+	}
+
+	/// Replaces `size` source bytes starting at `offset` with `data`
+	pub fn replace(&mut self, offset: u64, size: u64, data: ReadSlice<'a>) -> IOResult<()> {
+		self.remove(offset, size)?;
+		self.push_data(data)
+	}
+
+	/// Returns reference to the output
+	pub fn get_ref(&self) -> &W {
+		&self.output
+	}
+
+	/// Returns output without flushing it
+	///
+	/// Last edit may be still pending and is discarded, use `finish` to write it
+	pub fn into_inner(self) -> W {
+		self.output
+	}
+
+	/// Writes pending edit, flushes and returns output
+	///
+	/// Source bytes after the last block are dropped by `apply_diff`
+	pub fn finish(mut self) -> IOResult<W> {
+		self.flush_pending()?;
+		self.output.flush()?;
+		Ok(self.output)
+	}
+
+	/// Same as `finish`, but skips the rest of the source of `source_size` length, so it is kept
+	pub fn finish_with_source_size(mut self, source_size: u64) -> IOResult<W> {
+		self.seek_source(source_size)?;
+		self.finish()
+	}
+
+	fn seek_source(&mut self, offset: u64) -> IOResult<()> {
+		if offset < self.position {
+			return Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!(
+					"Edit at offset {} overlaps previous edit ending at {}",
+					offset, self.position
+				),
+			));
+		}
+		let size = offset - self.position;
+		self.push_sized(size, |size| DiffBlock::Skip { size })?;
+		self.position = offset;
+		Ok(())
+	}
+
+	fn push_sized<F: Fn(u32) -> DiffBlock<'a, u32>>(
+		&mut self,
+		mut size: u64,
+		block: F,
+	) -> IOResult<()> {
+		while size > 0 {
+			let part = min(size, u64::from(u32::MAX));
+			self.push(block(part as u32))?;
+			size -= part;
+		}
+		Ok(())
+	}
+
+	fn push_data(&mut self, data: ReadSlice<'a>) -> IOResult<()> {
+		let mut start = 0;
+		while start < data.size() {
+			let part = data.range(start, u64::from(u32::MAX));
+			start += part.size();
+			self.push(DiffBlock::Add { data: part })?;
+		}
+		Ok(())
+	}
+
+	fn push(&mut self, block: DiffBlock<'a, u32>) -> IOResult<()> {
+		let pending = match self.pending.take() {
+			Some(pending) => pending,
+			None => {
+				self.pending = Some(block);
+				return Ok(());
+			}
+		};
+		let max = u64::from(u32::MAX);
+		if pending.source_size() + block.source_size() > max
+			|| pending.target_size() + block.target_size() > max
+		{
+			self.pending = Some(block);
+			return self.write_raw(pending);
+		}
+		match pending + block {
+			(block, None) => {
+				self.pending = Some(block);
+				Ok(())
+			}
+			(done, Some(block)) => {
+				self.pending = Some(block);
+				self.write_raw(done)
+			}
+		}
+	}
+
+	fn flush_pending(&mut self) -> IOResult<()> {
+		match self.pending.take() {
+			Some(block) => self.write_raw(block),
+			None => Ok(()),
+		}
+	}
+
+	fn write_raw(&mut self, mut block: DiffBlock<u32>) -> IOResult<()> {
+		if let Some(data) = block.data_mut() {
+			if data.size() > u64::from(u32::MAX) {
+				return Err(Error::new(
+					ErrorKind::InvalidInput,
+					"Block data is longer than u32::MAX",
+				));
+			}
+			data.seek(SeekFrom::Start(0))?;
+		}
+		copy(&mut block.into_bytes(), &mut self.output)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod diff_writer_tests {
+	use super::DiffWriter;
+	use diff::{apply_diff, create_diff};
+	use diff_block::DiffBlock;
+	use diff_reader::DiffReader;
+	use readslice::ReadSlice;
+	use std::io::Cursor;
+	use test_mod::TextFile;
+
+	#[test]
+	fn round_trip_test() {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+
+		let mut input = ReadSlice::from(diff.clone());
+		let blocks = DiffReader::new(&mut input)
+			.collect::<Result<Vec<_>, _>>()
+			.unwrap();
+		assert!(blocks.len() > 1);
+
+		let mut writer = DiffWriter::new(vec![]);
+		writer.write_blocks(blocks).unwrap();
+		assert_eq!(writer.finish().unwrap(), diff);
+	}
+
+	#[test]
+	fn edits_test() {
+		let mut writer = DiffWriter::new(vec![]);
+		writer
+			.replace(2, 3, ReadSlice::from(b"ab".to_vec()))
+			.unwrap();
+		writer.insert(7, ReadSlice::from(b"xyz".to_vec())).unwrap();
+		writer.remove(8, 1).unwrap();
+		let diff = writer.finish_with_source_size(10).unwrap();
+
+		let mut restored = vec![];
+		apply_diff(
+			&mut Cursor::new(b"0123456789".to_vec()),
+			&mut Cursor::new(diff),
+			&mut restored,
+		)
+		.unwrap();
+		assert_eq!(restored, b"01ab56xyz79");
+	}
+
+	#[test]
+	fn merge_test() {
+		let mut writer = DiffWriter::new(vec![]);
+		writer.remove(2, 2).unwrap();
+		writer.insert(4, ReadSlice::from(b"zz".to_vec())).unwrap();
+		let diff = writer.finish().unwrap();
+		let mut input = ReadSlice::from(diff);
+		let blocks = DiffReader::new(&mut input).consume().unwrap();
+		assert_eq!(blocks.len(), 2);
+		match &blocks[1] {
+			DiffBlock::ReplaceWithSameLength { .. } => {}
+			x => panic!("Unexpected block {:?}", x),
+		}
+	}
+
+	#[test]
+	fn split_test() {
+		let max = u64::from(u32::MAX);
+		let mut writer = DiffWriter::new(vec![]);
+		writer.remove(max * 2 + 1, 1).unwrap();
+		assert_eq!(writer.source_position(), max * 2 + 2);
+		let diff = writer.finish().unwrap();
+		let mut input = ReadSlice::from(diff);
+		let sizes: Vec<_> = DiffReader::new(&mut input)
+			.map(|x| x.unwrap().source_size())
+			.collect();
+		assert_eq!(sizes, [max, max, 1, 1]);
+	}
+
+	#[test]
+	fn order_fail_test() {
+		let mut writer = DiffWriter::new(vec![]);
+		writer.remove(5, 3).unwrap();
+		assert!(writer.insert(6, ReadSlice::from(b"a".to_vec())).is_err());
+		assert!(writer.remove(4, 1).is_err());
+		assert!(writer.insert(8, ReadSlice::from(b"a".to_vec())).is_ok());
+	}
+}
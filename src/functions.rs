@@ -72,6 +72,26 @@ fn vec_to_u32_be_test() {
 	assert_eq!(vec_to_u32_be(&[0x00, 0x00, 0x00, 0x10]), 16);
 }
 
+/// Converts u8 slice to u64 BigEndian
+pub fn vec_to_u64_be(input: &[u8]) -> u64 {
+	let mut o: u64 = 0;
+	let len = input.len();
+	for (index, item) in input.iter().enumerate() {
+		let shift = len - index - 1;
+		let s = u64::from(*item) << (shift * 8);
+		o |= s;
+	}
+	o
+}
+
+#[test]
+fn vec_to_u64_be_test() {
+	assert_eq!(
+		vec_to_u64_be(&[0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x10]),
+		4_294_967_312
+	);
+}
+
 /// Converts u8 slice to u32 LittleEndian
 pub fn vec_to_usize_le(input: &[u8]) -> usize {
 	let mut o: usize = 0;
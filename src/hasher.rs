@@ -0,0 +1,352 @@
+//! Contains `BlockHasher` trait and its implementations
+//!
+//! Hasher is used to compute digests of the indexes blocks, which are then compared
+//! to find out which blocks were changed.
+//! [Sha256Hasher](struct.Sha256Hasher.html) is used by default,
+//! any other hasher can be passed to functions with `_with_hasher` suffix.
+
+use blake2b_simd::{Params as Blake2bParams, State as Blake2bState};
+use sha2::{Digest as Sha2Digest, Sha256};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::{ErrorKind, Read, Result as IOResult};
+use xxhash_rust::xxh3::Xxh3Default;
+use xxhash_rust::xxh64::Xxh64;
+
+/// Maximum digest length in bytes
+pub const MAX_DIGEST_LENGTH: usize = 32;
+
+/// Digest of the block
+///
+/// Digest is stored inline as fixed size byte array, so it can be copied and compared without allocations
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Digest {
+	bytes: [u8; MAX_DIGEST_LENGTH],
+	len: u8,
+}
+
+impl Digest {
+	/// Creates digest from bytes
+	///
+	/// Panics if `bytes` are longer than `MAX_DIGEST_LENGTH`
+	pub fn new(bytes: &[u8]) -> Self {
+		if bytes.len() > MAX_DIGEST_LENGTH {
+			panic!("Digest is too long");
+		}
+		let mut digest = Self {
+			bytes: [0; MAX_DIGEST_LENGTH],
+			len: bytes.len() as u8,
+		};
+		digest.bytes[..bytes.len()].clone_from_slice(bytes);
+		digest
+	}
+
+	/// Returns digest bytes
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes[..self.len as usize]
+	}
+
+	/// Returns lowercase hex representation of the digest
+	pub fn to_hex(&self) -> String {
+		self.as_bytes()
+			.iter()
+			.map(|b| format!("{:02x}", b))
+			.collect::<String>()
+	}
+}
+
+impl Debug for Digest {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		write!(f, "Digest({})", self.to_hex())
+	}
+}
+
+/// Incremental hashing state
+pub trait HasherState: Clone {
+	/// Feeds data to the state
+	fn update(&mut self, data: &[u8]);
+
+	/// Returns digest of all the data fed so far
+	fn finish(self) -> Digest;
+}
+
+/// Hash function used for block comparison
+pub trait BlockHasher {
+	type State: HasherState;
+
+	/// Name of the hash function. Is stored in signatures to make sure they are compared with the same hasher
+	fn name(&self) -> &str;
+
+	/// Returns fresh hashing state
+	fn start(&self) -> Self::State;
+
+	/// Computes digest of the input reader
+	fn hash<T: Read>(&self, input: &mut T) -> IOResult<Digest> {
+		let mut state = self.start();
+		let mut buf = [0u8; 1024 * 64];
+		loop {
+			match input.read(&mut buf) {
+				Ok(0) => break,
+				Ok(x) => state.update(&buf[0..x]),
+				Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(state.finish())
+	}
+}
+
+impl<H: BlockHasher> BlockHasher for &H {
+	type State = H::State;
+
+	fn name(&self) -> &str {
+		(**self).name()
+	}
+
+	fn start(&self) -> Self::State {
+		(**self).start()
+	}
+}
+
+/// SHA-256 hasher
+///
+/// Default hasher. Slowest of the built-in ones, but cryptographically strong
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sha256Hasher;
+
+/// `Sha256Hasher` state
+#[derive(Clone)]
+pub struct Sha256State(Sha256);
+
+impl HasherState for Sha256State {
+	fn update(&mut self, data: &[u8]) {
+		self.0.input(data);
+	}
+
+	fn finish(self) -> Digest {
+		Digest::new(&self.0.result())
+	}
+}
+
+impl BlockHasher for Sha256Hasher {
+	type State = Sha256State;
+
+	fn name(&self) -> &str {
+		"sha256"
+	}
+
+	fn start(&self) -> Self::State {
+		Sha256State(Sha256::default())
+	}
+}
+
+/// 64-bit xxHash hasher
+///
+/// Fast non-cryptographic hash with 8 byte digests
+#[derive(Clone, Copy, Debug, Default)]
+pub struct XxHash64Hasher;
+
+/// `XxHash64Hasher` state
+#[derive(Clone)]
+pub struct XxHash64State(Xxh64);
+
+impl HasherState for XxHash64State {
+	fn update(&mut self, data: &[u8]) {
+		self.0.update(data);
+	}
+
+	fn finish(self) -> Digest {
+		Digest::new(&self.0.digest().to_be_bytes())
+	}
+}
+
+impl BlockHasher for XxHash64Hasher {
+	type State = XxHash64State;
+
+	fn name(&self) -> &str {
+		"xxh64"
+	}
+
+	fn start(&self) -> Self::State {
+		XxHash64State(Xxh64::new(0))
+	}
+}
+
+/// 128-bit XXH3 hasher
+///
+/// Fast non-cryptographic hash with 16 byte digests
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Xxh3Hasher;
+
+/// `Xxh3Hasher` state
+#[derive(Clone)]
+pub struct Xxh3State(Box<Xxh3Default>);
+
+impl HasherState for Xxh3State {
+	fn update(&mut self, data: &[u8]) {
+		self.0.update(data);
+	}
+
+	fn finish(self) -> Digest {
+		Digest::new(&self.0.digest128().to_be_bytes())
+	}
+}
+
+impl BlockHasher for Xxh3Hasher {
+	type State = Xxh3State;
+
+	fn name(&self) -> &str {
+		"xxh3-128"
+	}
+
+	fn start(&self) -> Self::State {
+		Xxh3State(Box::new(Xxh3Default::new()))
+	}
+}
+
+/// BLAKE2b hasher
+///
+/// Cryptographic hash with 32 byte digests, faster than SHA-256 on 64-bit platforms
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Blake2bHasher;
+
+/// `Blake2bHasher` state
+#[derive(Clone)]
+pub struct Blake2bHasherState(Blake2bState);
+
+impl HasherState for Blake2bHasherState {
+	fn update(&mut self, data: &[u8]) {
+		self.0.update(data);
+	}
+
+	fn finish(self) -> Digest {
+		Digest::new(self.0.finalize().as_bytes())
+	}
+}
+
+impl BlockHasher for Blake2bHasher {
+	type State = Blake2bHasherState;
+
+	fn name(&self) -> &str {
+		"blake2b-256"
+	}
+
+	fn start(&self) -> Self::State {
+		Blake2bHasherState(
+			Blake2bParams::new()
+				.hash_length(MAX_DIGEST_LENGTH)
+				.to_state(),
+		)
+	}
+}
+
+/// Built-in hashers selectable at runtime
+///
+/// Useful when hasher is chosen by name, as when reading a persisted signature
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HashAlgorithm {
+	#[default]
+	Sha256,
+	XxHash64,
+	Xxh3,
+	Blake2b,
+}
+
+impl HashAlgorithm {
+	/// Returns built-in hasher by its name
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"sha256" => Some(HashAlgorithm::Sha256),
+			"xxh64" => Some(HashAlgorithm::XxHash64),
+			"xxh3-128" => Some(HashAlgorithm::Xxh3),
+			"blake2b-256" => Some(HashAlgorithm::Blake2b),
+			_ => None,
+		}
+	}
+}
+
+/// `HashAlgorithm` state
+#[derive(Clone)]
+pub enum HashAlgorithmState {
+	Sha256(Sha256State),
+	XxHash64(XxHash64State),
+	Xxh3(Xxh3State),
+	Blake2b(Blake2bHasherState),
+}
+
+impl HasherState for HashAlgorithmState {
+	fn update(&mut self, data: &[u8]) {
+		match self {
+			HashAlgorithmState::Sha256(state) => state.update(data),
+			HashAlgorithmState::XxHash64(state) => state.update(data),
+			HashAlgorithmState::Xxh3(state) => state.update(data),
+			HashAlgorithmState::Blake2b(state) => state.update(data),
+		}
+	}
+
+	fn finish(self) -> Digest {
+		match self {
+			HashAlgorithmState::Sha256(state) => state.finish(),
+			HashAlgorithmState::XxHash64(state) => state.finish(),
+			HashAlgorithmState::Xxh3(state) => state.finish(),
+			HashAlgorithmState::Blake2b(state) => state.finish(),
+		}
+	}
+}
+
+impl BlockHasher for HashAlgorithm {
+	type State = HashAlgorithmState;
+
+	fn name(&self) -> &str {
+		match self {
+			HashAlgorithm::Sha256 => "sha256",
+			HashAlgorithm::XxHash64 => "xxh64",
+			HashAlgorithm::Xxh3 => "xxh3-128",
+			HashAlgorithm::Blake2b => "blake2b-256",
+		}
+	}
+
+	fn start(&self) -> Self::State {
+		match self {
+			HashAlgorithm::Sha256 => HashAlgorithmState::Sha256(Sha256Hasher.start()),
+			HashAlgorithm::XxHash64 => HashAlgorithmState::XxHash64(XxHash64Hasher.start()),
+			HashAlgorithm::Xxh3 => HashAlgorithmState::Xxh3(Xxh3Hasher.start()),
+			HashAlgorithm::Blake2b => HashAlgorithmState::Blake2b(Blake2bHasher.start()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod hasher_tests {
+	use super::{BlockHasher, HashAlgorithm, Sha256Hasher};
+	use functions::compute_hash;
+	use std::io::Cursor;
+
+	#[test]
+	fn sha256_test() {
+		let data = b"hey fellas, have you heard the news".to_vec();
+		let digest = Sha256Hasher.hash(&mut Cursor::new(data.clone())).unwrap();
+		assert_eq!(digest.as_bytes().len(), 32);
+		assert_eq!(digest.to_hex(), compute_hash(&mut Cursor::new(data)));
+	}
+
+	#[test]
+	fn algorithms_test() {
+		let data = b"hey fellas, have you heard the news".to_vec();
+		let other = b"hey fellas, have you heard the newz".to_vec();
+		let algorithms = [
+			(HashAlgorithm::Sha256, 32),
+			(HashAlgorithm::XxHash64, 8),
+			(HashAlgorithm::Xxh3, 16),
+			(HashAlgorithm::Blake2b, 32),
+		];
+		for (algorithm, len) in algorithms.iter() {
+			assert_eq!(HashAlgorithm::from_name(algorithm.name()), Some(*algorithm));
+			let a = algorithm.hash(&mut Cursor::new(data.clone())).unwrap();
+			let b = algorithm.hash(&mut Cursor::new(data.clone())).unwrap();
+			let c = algorithm.hash(&mut Cursor::new(other.clone())).unwrap();
+			assert_eq!(a.as_bytes().len(), *len);
+			assert_eq!(a, b);
+			assert_ne!(a, c);
+		}
+	}
+}
@@ -0,0 +1,283 @@
+//! Contains in-place diff application
+//!
+//! Diff is applied to the source file itself, so no space for a second copy is needed.
+//! Target bytes are written behind the source bytes which are yet to be read, so it is
+//! safe as long as target doesn't outgrow the source consumed so far. Otherwise source
+//! bytes about to be overwritten are read ahead into memory, `plan_in_place` tells how much.
+
+use diff_block::DiffBlock;
+use diff_reader::DiffReader;
+use readslice::ReadSlice;
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom, Write};
+
+const CHUNK_SIZE: usize = 1024 * 64;
+
+/// Result of the in-place application analysis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InPlacePlan {
+	/// Size of the source covered by the diff
+	pub source_size: u64,
+	/// Size of the target
+	pub target_size: u64,
+	/// Number of source bytes which have to be held in memory at once
+	pub buffer_size: u64,
+}
+
+impl InPlacePlan {
+	/// Checks if diff can be applied without read-ahead buffer
+	pub fn is_safe(&self) -> bool {
+		self.buffer_size == 0
+	}
+}
+
+/// Analyses diff for in-place application, diff is read from its current position
+pub fn plan_in_place<D: Read + Seek>(diff: &mut D) -> IOResult<InPlacePlan> {
+	let mut input = ReadSlice::new(diff)?;
+	let mut reader = DiffReader::new(&mut input);
+	let mut plan = InPlacePlan {
+		source_size: 0,
+		target_size: 0,
+		buffer_size: 0,
+	};
+	let mut ends = vec![];
+	while let Some(block) = reader.next_block()? {
+		plan.source_size += block.source_size();
+		plan.target_size += block.target_size();
+		ends.push((plan.source_size, plan.target_size));
+	}
+	for (source_end, target_end) in ends {
+		let overrun = min(target_end, plan.source_size).saturating_sub(source_end);
+		plan.buffer_size = max(plan.buffer_size, overrun);
+	}
+	Ok(plan)
+}
+
+/// Source bytes of the file being overwritten
+///
+/// `ahead` holds bytes from `position` to `read_position`, which were read before
+/// the file was overwritten there
+struct Source {
+	ahead: VecDeque<u8>,
+	position: u64,
+	read_position: u64,
+	size: u64,
+}
+
+impl Source {
+	/// Reads file ahead, so writing up to `offset` doesn't lose source bytes
+	fn protect<F: Read + Seek>(&mut self, file: &mut F, offset: u64) -> IOResult<()> {
+		let offset = min(offset, self.size);
+		if self.read_position >= offset {
+			return Ok(());
+		}
+		file.seek(SeekFrom::Start(self.read_position))?;
+		let mut buf = vec![0; CHUNK_SIZE];
+		while self.read_position < offset {
+			let size = min(CHUNK_SIZE as u64, offset - self.read_position) as usize;
+			let read = file.read(&mut buf[..size])?;
+			if read == 0 {
+				return Err(Error::new(
+					ErrorKind::UnexpectedEof,
+					"Source is shorter than the diff expects",
+				));
+			}
+			self.ahead.extend(&buf[..read]);
+			self.read_position += read as u64;
+		}
+		Ok(())
+	}
+
+	/// Drops `size` source bytes
+	fn consume(&mut self, size: u64) {
+		let buffered = min(size, self.ahead.len() as u64) as usize;
+		self.ahead.drain(..buffered);
+		self.position += size;
+		self.read_position = max(self.read_position, self.position);
+	}
+
+	/// Takes up to `buf.len()` next source bytes
+	fn take<F: Read + Seek>(&mut self, file: &mut F, buf: &mut [u8]) -> IOResult<usize> {
+		self.protect(file, self.position + buf.len() as u64)?;
+		let size = min(buf.len(), self.ahead.len());
+		for (to, from) in buf.iter_mut().zip(self.ahead.drain(..size)) {
+			*to = from;
+		}
+		self.position += size as u64;
+		Ok(size)
+	}
+}
+
+/// Applies diff to the file in place, returns size of the target
+///
+/// Diff is read from its current position. Source bytes after the target end are left
+/// in the file, use `apply_diff_in_place_to_file` to truncate them.
+/// Fails before the file is changed if more than `max_buffer` bytes have to be read ahead
+/// (see `plan_in_place`), in that case use `apply_diff` with a separate output
+pub fn apply_diff_in_place<F: Read + Write + Seek, D: Read + Seek>(
+	file: &mut F,
+	diff: &mut D,
+	max_buffer: u64,
+) -> IOResult<u64> {
+	let start = diff.stream_position()?;
+	let plan = plan_in_place(diff)?;
+	if plan.buffer_size > max_buffer {
+		return Err(Error::new(
+			ErrorKind::InvalidInput,
+			format!(
+				"In-place apply needs {} bytes of buffer, {} allowed",
+				plan.buffer_size, max_buffer
+			),
+		));
+	}
+	diff.seek(SeekFrom::Start(start))?;
+
+	let mut input = ReadSlice::new(diff)?;
+	let mut reader = DiffReader::new(&mut input);
+	let mut source = Source {
+		ahead: VecDeque::new(),
+		position: 0,
+		read_position: 0,
+		size: plan.source_size,
+	};
+	let mut position = 0;
+	let mut buf = vec![0; CHUNK_SIZE];
+	while let Some(block) = reader.next_block()? {
+		let (remove_size, data) = match block {
+			DiffBlock::Skip { size } => {
+				let mut size = u64::from(size);
+				if position == source.position {
+					source.consume(size);
+					position += size;
+					size = 0;
+				}
+				while size > 0 {
+					let part = min(size, CHUNK_SIZE as u64) as usize;
+					let read = source.take(file, &mut buf[..part])?;
+					if read == 0 {
+						return Err(Error::new(
+							ErrorKind::UnexpectedEof,
+							"Source is shorter than the diff expects",
+						));
+					}
+					write_at(file, &mut source, position, &buf[..read])?;
+					position += read as u64;
+					size -= read as u64;
+				}
+				continue;
+			}
+			DiffBlock::Remove { size } => (u64::from(size), None),
+			DiffBlock::Add { data } => (0, Some(data)),
+			DiffBlock::Replace { remove_size, data } => (u64::from(remove_size), Some(data)),
+			DiffBlock::ReplaceWithSameLength { data } => (data.size(), Some(data)),
+		};
+		source.consume(remove_size);
+		if let Some(mut data) = data {
+			data.rewind()?;
+			loop {
+				let read = data.read(&mut buf)?;
+				if read == 0 {
+					break;
+				}
+				write_at(file, &mut source, position, &buf[..read])?;
+				position += read as u64;
+			}
+		}
+	}
+	file.flush()?;
+	Ok(position)
+}
+
+/// Same as `apply_diff_in_place`, but also truncates the file to the target size
+pub fn apply_diff_in_place_to_file<D: Read + Seek>(
+	file: &mut File,
+	diff: &mut D,
+	max_buffer: u64,
+) -> IOResult<u64> {
+	let size = apply_diff_in_place(file, diff, max_buffer)?;
+	file.set_len(size)?;
+	Ok(size)
+}
+
+fn write_at<F: Read + Write + Seek>(
+	file: &mut F,
+	source: &mut Source,
+	offset: u64,
+	data: &[u8],
+) -> IOResult<()> {
+	source.protect(file, offset + data.len() as u64)?;
+	file.seek(SeekFrom::Start(offset))?;
+	file.write_all(data)
+}
+
+#[cfg(test)]
+mod in_place_tests {
+	use super::{apply_diff_in_place, plan_in_place};
+	use diff::{apply_diff, create_diff};
+	use diff_writer::DiffWriter;
+	use readslice::ReadSlice;
+	use std::fs::read;
+	use std::io::Cursor;
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let files = [
+			"./test_data/a_a.txt",
+			"./test_data/a_b.txt",
+			"./test_data/a_c.txt",
+			"./test_data/a_d.txt",
+		];
+		for a in files.iter() {
+			for b in files.iter() {
+				let mut diff = Cursor::new(vec![]);
+				create_diff(
+					&mut TextFile::from_path(a),
+					&mut TextFile::from_path(b),
+					&mut diff,
+				)
+				.unwrap();
+				let mut expected = vec![];
+				diff.set_position(0);
+				apply_diff(&mut Cursor::new(read(a).unwrap()), &mut diff, &mut expected).unwrap();
+
+				diff.set_position(0);
+				let mut file = Cursor::new(read(a).unwrap());
+				let size = apply_diff_in_place(&mut file, &mut diff, u64::MAX).unwrap();
+				let mut result = file.into_inner();
+				result.truncate(size as usize);
+				assert_eq!(result, expected, "{} -> {}", a, b);
+			}
+		}
+	}
+
+	#[test]
+	fn plan_test() {
+		let mut writer = DiffWriter::new(vec![]);
+		writer
+			.replace(2, 3, ReadSlice::from(b"ab".to_vec()))
+			.unwrap();
+		writer.remove(6, 2).unwrap();
+		let mut diff = Cursor::new(writer.finish_with_source_size(10).unwrap());
+		let plan = plan_in_place(&mut diff).unwrap();
+		assert!(plan.is_safe());
+		assert_eq!((plan.source_size, plan.target_size), (10, 7));
+
+		let mut writer = DiffWriter::new(vec![]);
+		writer.insert(1, ReadSlice::from(b"xyz".to_vec())).unwrap();
+		writer.remove(4, 4).unwrap();
+		let mut diff = Cursor::new(writer.finish_with_source_size(10).unwrap());
+		let plan = plan_in_place(&mut diff).unwrap();
+		assert_eq!(plan.buffer_size, 3);
+
+		diff.set_position(0);
+		let mut file = Cursor::new(b"0123456789".to_vec());
+		assert!(apply_diff_in_place(&mut file, &mut diff, 2).is_err());
+		assert_eq!(file.get_ref(), b"0123456789");
+		diff.set_position(0);
+		let size = apply_diff_in_place(&mut file, &mut diff, 3).unwrap();
+		assert_eq!(&file.get_ref()[..size as usize], b"0xyz12389");
+	}
+}
@@ -1,6 +1,7 @@
 //! Module with `Indexes` struct and `WithIndexes` trait
 
-use std::io::{Read, Seek};
+use functions::{u32_to_u8_be_vec, u64_to_u8_be_vec, vec_to_u32_be, vec_to_u64_be};
+use std::io::{Read, Result as IOResult, Seek, SeekFrom};
 use std::path::PathBuf;
 
 /// Indexes struct
@@ -29,6 +30,9 @@ use std::path::PathBuf;
 /// Diff algorithm uses this blocks to compute hashes and compare them one by one
 ///
 /// Indexes implementation varies from format to format and is supposed to be implemented manually for each format
+///
+/// Text representation can be produced and parsed with `to_text` and `from_text`,
+/// compact binary representation with `to_bytes` and `from_bytes`
 #[derive(PartialEq, Eq, Debug, Clone, Default)]
 pub struct Indexes {
 	order: Vec<(String, u64, u64)>,
@@ -139,6 +143,99 @@ impl Indexes {
 
 		out
 	}
+
+	/// Returns text representation of `Indexes`
+	///
+	/// Each item is written on its own line as `label start size`.
+	/// Labels may contain spaces, but not line breaks.
+	pub fn to_text(&self) -> String {
+		self.order
+			.iter()
+			.map(|(label, start, size)| format!("{} {} {}\n", label, start, size))
+			.collect()
+	}
+
+	/// Parses `Indexes` from text representation produced by `to_text`
+	///
+	/// Empty lines are ignored
+	pub fn from_text(input: &str) -> Result<Self, String> {
+		let mut out = Self::new();
+		for (index, line) in input.lines().enumerate() {
+			if line.trim().is_empty() {
+				continue;
+			}
+			let mut parts = line.rsplitn(3, ' ');
+			let size = parts.next().and_then(|x| x.parse::<u64>().ok());
+			let start = parts.next().and_then(|x| x.parse::<u64>().ok());
+			let label = parts.next();
+			match (label, start, size) {
+				(Some(label), Some(start), Some(size)) => {
+					if out.has(label) {
+						return Err(format!(
+							"Duplicate label \"{}\" at line {}",
+							label,
+							index + 1
+						));
+					}
+					out.insert(label.to_string(), start, size);
+				}
+				_ => return Err(format!("Malformed indexes line {}", index + 1)),
+			}
+		}
+		Ok(out)
+	}
+
+	/// Returns binary representation of `Indexes`
+	///
+	/// Format is BigEndian:
+	/// ```bash
+	/// items_count : 4
+	/// item_{n} : {...}
+	///   label_length : 4
+	///   label : label_length // utf-8
+	///   start : 8
+	///   size : 8
+	/// ```
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out = vec![];
+		out.extend_from_slice(&u32_to_u8_be_vec(self.order.len() as u32));
+		for (label, start, size) in &self.order {
+			out.extend_from_slice(&u32_to_u8_be_vec(label.len() as u32));
+			out.extend_from_slice(label.as_bytes());
+			out.extend_from_slice(&u64_to_u8_be_vec(*start));
+			out.extend_from_slice(&u64_to_u8_be_vec(*size));
+		}
+		out
+	}
+
+	/// Parses `Indexes` from binary representation produced by `to_bytes`
+	pub fn from_bytes(input: &[u8]) -> Result<Self, String> {
+		let mut out = Self::new();
+		let mut pos = 0;
+		let mut take = |len: usize| -> Result<&[u8], String> {
+			if input.len() - pos < len {
+				return Err("Unexpected end of indexes data".to_string());
+			}
+			pos += len;
+			Ok(&input[pos - len..pos])
+		};
+		let count = vec_to_u32_be(take(4)?);
+		for _ in 0..count {
+			let label_length = vec_to_u32_be(take(4)?) as usize;
+			let label = String::from_utf8(take(label_length)?.to_vec())
+				.map_err(|_| "Label is not a valid utf-8 string".to_string())?;
+			let start = vec_to_u64_be(take(8)?);
+			let size = vec_to_u64_be(take(8)?);
+			if out.has(&label) {
+				return Err(format!("Duplicate label \"{}\"", label));
+			}
+			out.insert(label, start, size);
+		}
+		if pos != input.len() {
+			return Err("Unexpected data after indexes".to_string());
+		}
+		Ok(out)
+	}
 }
 
 impl Iterator for Indexes {
@@ -184,6 +281,48 @@ impl<'a, T: WithIndexes> WithIndexes for &'a mut T {
 	}
 }
 
+/// Pairs arbitrary `Read + Seek` input with precomputed `Indexes`
+///
+/// Useful for formats which are parsed elsewhere and which indexes come as a sidecar file
+/// (see `Indexes::from_text` and `Indexes::from_bytes`)
+pub struct IndexedFile<T: Read + Seek> {
+	file: T,
+	indexes: Indexes,
+}
+
+impl<T: Read + Seek> IndexedFile<T> {
+	/// Creates new `IndexedFile` instance
+	pub fn new(file: T, indexes: Indexes) -> Self {
+		Self { file, indexes }
+	}
+
+	/// Returns inner file
+	pub fn into_inner(self) -> T {
+		self.file
+	}
+}
+
+impl<T: Read + Seek> Read for IndexedFile<T> {
+	fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+		self.file.read(buffer)
+	}
+}
+
+impl<T: Read + Seek> Seek for IndexedFile<T> {
+	fn seek(&mut self, from: SeekFrom) -> IOResult<u64> {
+		self.file.seek(from)
+	}
+}
+
+impl<T: Read + Seek> WithIndexes for IndexedFile<T> {
+	fn get_indexes(&mut self) -> Result<Indexes, String> {
+		Ok(Indexes {
+			order: self.indexes.order.clone(),
+			pos: 0,
+		})
+	}
+}
+
 #[cfg(test)]
 mod indexes_tests {
 	use super::Indexes;
@@ -231,4 +370,40 @@ mod indexes_tests {
 
 		assert_eq!(ends, model);
 	}
+
+	#[test]
+	fn text_round_trip_test() {
+		let mut ind = Indexes::new();
+		ind.insert("header".to_string(), 0, 16);
+		ind.insert("data item 1".to_string(), 16, 2);
+		ind.insert("misc_info".to_string(), 18, 8);
+
+		let text = ind.to_text();
+		assert_eq!(text, "header 0 16\ndata item 1 16 2\nmisc_info 18 8\n");
+		assert_eq!(Indexes::from_text(&text).unwrap(), ind);
+	}
+
+	#[test]
+	fn text_fail_test() {
+		assert_eq!(
+			Indexes::from_text("header 0 16\nline_1 16\n").unwrap_err(),
+			"Malformed indexes line 2"
+		);
+		assert_eq!(
+			Indexes::from_text("header 0 16\n\nheader 16 2\n").unwrap_err(),
+			"Duplicate label \"header\" at line 3"
+		);
+	}
+
+	#[test]
+	fn bytes_round_trip_test() {
+		let mut ind = Indexes::new();
+		ind.insert("header".to_string(), 0, 16);
+		ind.insert("line_1/data".to_string(), 16, 5_000_000_000);
+
+		let bytes = ind.to_bytes();
+		assert_eq!(bytes.len(), 4 + (4 + 6 + 16) + (4 + 11 + 16));
+		assert_eq!(Indexes::from_bytes(&bytes).unwrap(), ind);
+		assert!(Indexes::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+	}
 }
@@ -4,7 +4,27 @@
 //!
 //! # Usage
 //! To make custom library for diffing you own format you need to implement [WithIndexes](../bin_diff/indexes/trait.WithIndexes.html) trait on your input which also must implement `Read` and `Seek` traits.
-//! Then you may use created struct with [create_diff](../bin_diff/diff/fn.create_diff.html) and [apply_diff](../bin_diff/diff/fn.apply_diff.html) functions. Also [combine_diffs](../bin_diff/diff/fn.combine_diffs.html), [combine_diffs_vec](../bin_diff/diff/fn.combine_diffs_vec.html) functions are available.
+//! Then you may use created struct with [create_diff](../bin_diff/diff/fn.create_diff.html) and [apply_diff](../bin_diff/diff/fn.apply_diff.html) functions. Also [combine_diffs](../bin_diff/diff/fn.combine_diffs.html), [combine_diffs_vec](../bin_diff/diff/fn.combine_diffs_vec.html), [normalize_diff](../bin_diff/diff/fn.normalize_diff.html) and [diffs_equivalent](../bin_diff/diff/fn.diffs_equivalent.html) functions are available.
+//!
+//! Patched file can be read at any offset without writing it out with [PatchedFile](../bin_diff/patched_file/struct.PatchedFile.html), which uses [DiffIndex](../bin_diff/diff_index/struct.DiffIndex.html) of the diff.
+//!
+//! Diffs which don't grow the file much can be applied without a separate output with [apply_diff_in_place](../bin_diff/in_place/fn.apply_diff_in_place.html).
+//!
+//! To patch files on disk without leaving truncated results behind use [apply_diff_to_path](../bin_diff/atomic_apply/fn.apply_diff_to_path.html).
+//!
+//! Long applications can be resumed after a crash with [resume_apply_diff](../bin_diff/checkpoint/fn.resume_apply_diff.html).
+//!
+//! To locate truncated diffs and detect sources not matching the diff use [apply_diff_strict](../bin_diff/strict/fn.apply_diff_strict.html).
+//!
+//! Progress of long operations can be observed and cancelled with `_observed` variants, see [progress](../bin_diff/progress/index.html).
+//!
+//! Diffs arriving in chunks, e.g. from the network, can be decoded with [Decoder](../bin_diff/decoder/struct.Decoder.html).
+//!
+//! With `async` feature diffs can be created and applied on tokio streams, see [async_diff](../bin_diff/async_diff/index.html).
+//!
+//! Sub-ranges of your file, for example blocks described by `Indexes`, can be exposed with [ReadSlice](../bin_diff/readslice/struct.ReadSlice.html).
+//!
+//! If indexes of your format are computed elsewhere, they can be loaded with `Indexes::from_text` or `Indexes::from_bytes` and paired with the file by [IndexedFile](../bin_diff/indexes/struct.IndexedFile.html).
 //!
 //! # Binary diff format specification
 //!
@@ -121,21 +141,46 @@
 //! replace(x, y) | replace(z, w) = remove(x) next(add(y), replace(z, w))
 //! ```
 
+extern crate blake2b_simd;
 extern crate difference;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
 extern crate sha2;
+#[cfg(feature = "async")]
+extern crate tokio;
+extern crate xxhash_rust;
 
 #[cfg(test)]
 mod test_mod;
 
+#[cfg(feature = "async")]
+pub mod async_diff;
+pub mod atomic_apply;
 mod bytes_serializer;
+pub mod checkpoint;
 mod cmp;
+mod counting;
+pub mod decoder;
 pub mod diff;
-mod diff_block;
+pub mod diff_block;
+pub mod diff_index;
 mod diff_iterator;
-mod diff_reader;
-mod drain;
+pub mod diff_reader;
+pub mod diff_writer;
 pub mod functions;
+pub mod hasher;
+pub mod in_place;
 pub mod indexes;
 pub mod lines_with_hash_iterator;
-mod readseek;
-mod readslice;
+pub mod merge;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+mod monitor;
+pub mod patched_file;
+pub mod progress;
+pub mod readat;
+pub mod readseek;
+pub mod readslice;
+mod segments;
+pub mod signature;
+pub mod strict;
@@ -1,22 +1,33 @@
 //! Contains `LinesWithHashIterator`
 
-use functions::compute_hash;
+use hasher::{BlockHasher, Digest, Sha256Hasher};
 use indexes::{Indexes, WithIndexes};
-use std::io::{Read, SeekFrom};
+use std::cmp::min;
+use std::io::{Read, Result as IOResult, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{available_parallelism, scope};
 
 /// Yields indexes with appended hashes
-pub struct LinesWithHashIterator<T: WithIndexes> {
+pub struct LinesWithHashIterator<T: WithIndexes, H: BlockHasher = Sha256Hasher> {
 	file: T,
 	indexes: Indexes,
+	hasher: H,
 	pos: usize,
 }
 
-impl<T: WithIndexes> LinesWithHashIterator<T> {
-	pub fn new(mut file: T) -> Result<Self, String> {
+impl<T: WithIndexes> LinesWithHashIterator<T, Sha256Hasher> {
+	pub fn new(file: T) -> Result<Self, String> {
+		Self::with_hasher(file, Sha256Hasher)
+	}
+}
+
+impl<T: WithIndexes, H: BlockHasher> LinesWithHashIterator<T, H> {
+	pub fn with_hasher(mut file: T, hasher: H) -> Result<Self, String> {
 		let indexes = file.get_indexes()?.get_ends();
 		Ok(Self {
 			file,
 			indexes,
+			hasher,
 			pos: 0,
 		})
 	}
@@ -26,21 +37,122 @@ impl<T: WithIndexes> LinesWithHashIterator<T> {
 	}
 }
 
-impl<T: WithIndexes> Iterator for LinesWithHashIterator<T> {
-	type Item = (String, u64, u64, String);
+impl<T: WithIndexes, H: BlockHasher> Iterator for LinesWithHashIterator<T, H> {
+	type Item = Result<(String, u64, u64, Digest), String>;
 
 	fn next(&mut self) -> Option<Self::Item> {
 		if self.pos >= self.indexes.len() {
 			return None;
 		};
 		let item = self.indexes.next().unwrap();
-		self.file.seek(SeekFrom::Start(item.1)).unwrap();
-		let hash = {
-			let mut sl = &mut self.file.by_ref().take(item.2);
-			compute_hash(&mut sl)
-		};
-
 		self.pos += 1;
-		Some((item.0, item.1, item.2, hash))
+		let hash = self
+			.file
+			.seek(SeekFrom::Start(item.1))
+			.and_then(|_| {
+				let mut sl = &mut self.file.by_ref().take(item.2);
+				self.hasher.hash(&mut sl)
+			})
+			.map_err(|e| e.to_string());
+
+		Some(hash.map(|hash| (item.0, item.1, item.2, hash)))
+	}
+}
+
+/// Computes hashes of the file blocks on multiple threads
+///
+/// `open` is called once per worker thread and must return independent instance of the same file,
+/// for example by reopening it by path or by cloning a byte buffer.
+/// Blocks are distributed between `threads` workers (`0` means number of available cores),
+/// but output is always in the indexes order and is equal to what `LinesWithHashIterator` yields.
+pub fn hash_lines_parallel<T, F, H>(
+	open: F,
+	hasher: H,
+	threads: usize,
+) -> Result<Vec<(String, u64, u64, Digest)>, String>
+where
+	T: WithIndexes,
+	F: Fn() -> IOResult<T> + Sync,
+	H: BlockHasher + Sync,
+{
+	let indexes: Vec<_> = open()
+		.map_err(|e| e.to_string())?
+		.get_indexes()?
+		.get_ends()
+		.collect();
+	let threads = match threads {
+		0 => available_parallelism().map(|x| x.get()).unwrap_or(1),
+		x => x,
+	};
+	let threads = min(threads, indexes.len());
+
+	let next = AtomicUsize::new(0);
+	let results = scope(|s| {
+		let workers: Vec<_> = (0..threads)
+			.map(|_| {
+				s.spawn(|| -> Result<Vec<(usize, Digest)>, String> {
+					let mut file = open().map_err(|e| e.to_string())?;
+					let mut out = vec![];
+					loop {
+						let index = next.fetch_add(1, Ordering::Relaxed);
+						if index >= indexes.len() {
+							break;
+						}
+						let (_, start, size) = &indexes[index];
+						file.seek(SeekFrom::Start(*start))
+							.map_err(|e| e.to_string())?;
+						let hash = hasher
+							.hash(&mut file.by_ref().take(*size))
+							.map_err(|e| e.to_string())?;
+						out.push((index, hash));
+					}
+					Ok(out)
+				})
+			})
+			.collect();
+		workers
+			.into_iter()
+			.map(|x| {
+				x.join()
+					.unwrap_or_else(|_| Err("Hashing thread panicked".to_string()))
+			})
+			.collect::<Result<Vec<_>, String>>()
+	})?;
+
+	let mut hashes = vec![None; indexes.len()];
+	for (index, hash) in results.into_iter().flatten() {
+		hashes[index] = Some(hash);
+	}
+	Ok(indexes
+		.into_iter()
+		.zip(hashes)
+		.map(|((label, start, size), hash)| (label, start, size, hash.unwrap()))
+		.collect())
+}
+
+#[cfg(test)]
+mod lines_with_hash_iterator_tests {
+	use super::{hash_lines_parallel, LinesWithHashIterator};
+	use hasher::XxHash64Hasher;
+	use test_mod::TextFile;
+
+	#[test]
+	fn parallel_test() {
+		let lines: Vec<_> = LinesWithHashIterator::with_hasher(
+			TextFile::from_path("./test_data/a_d.txt"),
+			XxHash64Hasher,
+		)
+		.unwrap()
+		.collect::<Result<_, _>>()
+		.unwrap();
+		for threads in [0, 1, 3, 64].iter() {
+			let plines = hash_lines_parallel(
+				|| Ok(TextFile::from_path("./test_data/a_d.txt")),
+				XxHash64Hasher,
+				*threads,
+			)
+			.unwrap();
+			assert_eq!(lines, plines);
+		}
 	}
 }
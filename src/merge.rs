@@ -0,0 +1,672 @@
+//! Contains three-way merge of diffs
+//!
+//! Two diffs of the same base file are merged into one diff applying both sets of changes.
+//! Each diff is seen as a list of edits, where every run of changed blocks between skips is one edit
+//! replacing a range of the base with new data. Edits of both diffs are merged if they touch
+//! different ranges of the base, otherwise they are reported as conflicts.
+
+use diff::create_diff;
+use diff_block::DiffBlock;
+use diff_reader::DiffReader;
+use diff_writer::DiffWriter;
+use indexes::{IndexedFile, Indexes, WithIndexes};
+use lines_with_hash_iterator::LinesWithHashIterator;
+use readslice::ReadSlice;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+/// Replacement of `size` base bytes starting at `start` with `data`
+#[derive(Clone, Debug)]
+struct Edit<'a> {
+	start: u64,
+	size: u64,
+	data: ReadSlice<'a>,
+}
+
+impl<'a> Edit<'a> {
+	fn end(&self) -> u64 {
+		self.start + self.size
+	}
+
+	fn range(&self) -> Range<u64> {
+		self.start..self.end()
+	}
+
+	fn same_as(&self, other: &Edit) -> IOResult<bool> {
+		if self.start != other.start
+			|| self.size != other.size
+			|| self.data.size() != other.data.size()
+		{
+			return Ok(false);
+		}
+		Ok(read_all(&self.data)? == read_all(&other.data)?)
+	}
+
+	fn conflicts_with(&self, other: &Edit) -> bool {
+		match (self.size, other.size) {
+			(0, 0) => self.start == other.start,
+			(0, _) => other.start < self.start && self.start < other.end(),
+			(_, 0) => self.start < other.start && other.start < self.end(),
+			_ => self.start < other.end() && other.start < self.end(),
+		}
+	}
+}
+
+fn read_all(data: &ReadSlice) -> IOResult<Vec<u8>> {
+	let mut data = data.clone();
+	let mut out = vec![];
+	data.seek(SeekFrom::Start(0))?;
+	data.read_to_end(&mut out)?;
+	Ok(out)
+}
+
+/// Reads edits of the diff and size of the base it covers
+fn read_edits<'b>(mut reader: DiffReader<'_, 'b>) -> IOResult<(Vec<Edit<'b>>, u64)> {
+	let mut edits = vec![];
+	let mut position = 0;
+	let mut current: Option<(u64, u64, Vec<ReadSlice>)> = None;
+	while let Some(block) = reader.next_block()? {
+		if block.is_empty() {
+			continue;
+		}
+		if let DiffBlock::Skip { size } = block {
+			edits.extend(current.take().map(into_edit));
+			position += u64::from(size);
+			continue;
+		}
+		let size = block.source_size();
+		let (start, edit_size, mut data) = current.take().unwrap_or((position, 0, vec![]));
+		data.extend(block.data().map(|x| x.range(0, x.size())));
+		current = Some((start, edit_size + size, data));
+		position += size;
+	}
+	edits.extend(current.map(into_edit));
+	Ok((edits, position))
+}
+
+fn into_edit((start, size, data): (u64, u64, Vec<ReadSlice>)) -> Edit {
+	Edit {
+		start,
+		size,
+		data: ReadSlice::concat(data),
+	}
+}
+
+/// Pair of conflicting changes
+///
+/// Ranges are in the base file coordinates, empty range means insertion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+	pub ours: Range<u64>,
+	pub theirs: Range<u64>,
+}
+
+/// Result of the merge
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeResult {
+	/// Merged diff was written to the output
+	Merged,
+	/// Diffs change the same ranges of the base, nothing was written
+	Conflicts(Vec<Conflict>),
+}
+
+/// Side of the merge the edit comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+	Ours,
+	Theirs,
+}
+
+/// Edits of both diffs in the order of the merged diff and size of the base they cover
+type MergedEdits<'a> = (Vec<(Side, Edit<'a>)>, u64);
+
+/// Reads and merges edits of two diffs of the same base
+///
+/// Identical edits are taken once and are attributed to `theirs`
+fn merge_edits<'a, T: 'a + Read + Seek, U: 'a + Read + Seek>(
+	ours: T,
+	theirs: U,
+) -> IOResult<Result<MergedEdits<'a>, Vec<Conflict>>> {
+	let mut ours = ReadSlice::new(ours)?;
+	let (ours, ours_size) = read_edits(DiffReader::new(&mut ours))?;
+	let mut theirs = ReadSlice::new(theirs)?;
+	let (theirs, theirs_size) = read_edits(DiffReader::new(&mut theirs))?;
+	let (ours, theirs, size) = with_truncation(ours, ours_size, theirs, theirs_size);
+
+	let mut conflicts = vec![];
+	let mut first = 0;
+	for a in &ours {
+		while first < theirs.len() && theirs[first].end() < a.start {
+			first += 1;
+		}
+		for b in theirs[first..].iter().take_while(|b| b.start <= a.end()) {
+			if a.conflicts_with(b) && !a.same_as(b)? {
+				conflicts.push(Conflict {
+					ours: a.range(),
+					theirs: b.range(),
+				});
+			}
+		}
+	}
+	if !conflicts.is_empty() {
+		return Ok(Err(conflicts));
+	}
+
+	let mut edits: Vec<_> = ours
+		.into_iter()
+		.map(|x| (Side::Ours, x))
+		.chain(theirs.into_iter().map(|x| (Side::Theirs, x)))
+		.collect();
+	edits.sort_by_key(|(_, x)| (x.start, x.size > 0));
+	let mut merged: Vec<(Side, Edit)> = vec![];
+	for (side, edit) in edits {
+		if let Some(last) = merged.last_mut() {
+			if last.1.same_as(&edit)? {
+				last.0 = Side::Theirs;
+				continue;
+			}
+		}
+		merged.push((side, edit));
+	}
+	Ok(Ok((merged, size)))
+}
+
+/// Merges two diffs of the same base file
+///
+/// Writes diff applying changes of both `ours` and `theirs` if they touch different ranges of the base.
+/// Identical changes made by both sides are taken once
+pub fn merge_diffs<'a, T: 'a + Read + Seek, U: 'a + Read + Seek, W: Write>(
+	ours: T,
+	theirs: U,
+	output: &mut W,
+) -> IOResult<MergeResult> {
+	let (edits, size) = match merge_edits(ours, theirs)? {
+		Ok(merged) => merged,
+		Err(conflicts) => return Ok(MergeResult::Conflicts(conflicts)),
+	};
+	let mut writer = DiffWriter::new(output);
+	for (_, edit) in edits {
+		writer.replace(edit.start, edit.size, edit.data)?;
+	}
+	writer.finish_with_source_size(size)?;
+	Ok(MergeResult::Merged)
+}
+
+/// Rebases `diff` onto `onto` diff of the same base file
+///
+/// Given diffs A→B and A→C writes diff C→D, where D has changes of both B and C.
+/// This is the transform operation, see `diff_operations.md`.
+/// Changes of `diff` already made by `onto` are skipped. If both diffs change the same ranges of the base
+/// conflicts are returned in the base coordinates, `ours` being `diff`, and nothing is written
+pub fn rebase_diff<'a, T: 'a + Read + Seek, U: 'a + Read + Seek, W: Write>(
+	diff: T,
+	onto: U,
+	output: &mut W,
+) -> IOResult<MergeResult> {
+	let (edits, size) = match merge_edits(diff, onto)? {
+		Ok(merged) => merged,
+		Err(conflicts) => return Ok(MergeResult::Conflicts(conflicts)),
+	};
+	let mut writer = DiffWriter::new(output);
+	let mut shift: i64 = 0;
+	for (side, edit) in edits {
+		match side {
+			Side::Ours => {
+				let start = (edit.start as i64 + shift) as u64;
+				writer.replace(start, edit.size, edit.data)?;
+			}
+			Side::Theirs => shift += edit.data.size() as i64 - edit.size as i64,
+		}
+	}
+	writer.finish_with_source_size((size as i64 + shift) as u64)?;
+	Ok(MergeResult::Merged)
+}
+
+/// Applies to the diff covering less of the base removal of the rest
+///
+/// `apply_diff` drops source bytes after the last block, so such diff truncates the base
+fn with_truncation<'a>(
+	mut ours: Vec<Edit<'a>>,
+	ours_size: u64,
+	mut theirs: Vec<Edit<'a>>,
+	theirs_size: u64,
+) -> (Vec<Edit<'a>>, Vec<Edit<'a>>, u64) {
+	let size = ours_size.max(theirs_size);
+	let truncate = |edits: &mut Vec<Edit<'a>>, edits_size: u64| {
+		if edits_size < size {
+			edits.push(Edit {
+				start: edits_size,
+				size: size - edits_size,
+				data: ReadSlice::from(vec![]),
+			});
+		}
+	};
+	truncate(&mut ours, ours_size);
+	truncate(&mut theirs, theirs_size);
+	(ours, theirs, size)
+}
+
+/// Label changed by both sides in different ways
+///
+/// Contents are `None` if the side doesn't have the label
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelConflict {
+	pub label: String,
+	pub base: Option<Vec<u8>>,
+	pub ours: Option<Vec<u8>>,
+	pub theirs: Option<Vec<u8>>,
+}
+
+/// Resolution of the `LabelConflict`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+	/// Take our version, remove the label if we removed it
+	Ours,
+	/// Take their version, remove the label if they removed it
+	Theirs,
+	/// Keep base version, remove the label if it was added
+	Base,
+	/// Use custom contents
+	Data(Vec<u8>),
+	/// Remove the label
+	Remove,
+}
+
+/// Contents of the label in the merged file
+enum Choice {
+	Ours(u64, u64),
+	Theirs(u64, u64),
+	Data(Vec<u8>),
+}
+
+/// Place of the label added by theirs in the merged file
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Place {
+	Start,
+	/// After the label with given position in merged labels of ours
+	After(usize),
+}
+
+/// Merges two edited versions of the base file label by label and writes diff of the base to the merged file
+///
+/// Labels are the ends of `Indexes` and are compared by their hashes. Label changed only by one side
+/// takes its version, label changed by both sides in different ways is passed to the `resolver`.
+/// Labels keep order of `ours`, labels added by `theirs` follow the nearest preceding label
+/// which is kept in the merged file.
+/// Indexes are expected to cover whole files
+pub fn merge_labeled<B, O, T, W, R>(
+	base: &mut B,
+	ours: &mut O,
+	theirs: &mut T,
+	output: &mut W,
+	mut resolver: R,
+) -> IOResult<()>
+where
+	B: WithIndexes,
+	O: WithIndexes,
+	T: WithIndexes,
+	W: Write,
+	R: FnMut(&LabelConflict) -> Resolution,
+{
+	let to_error = |e: String| Error::new(ErrorKind::InvalidData, e);
+	let base_lines: HashMap<_, _> = LinesWithHashIterator::new(&mut *base)
+		.map_err(to_error)?
+		.map(|x| x.map(|(label, start, size, hash)| (label, (start, size, hash))))
+		.collect::<Result<_, _>>()
+		.map_err(to_error)?;
+	let ours_lines: Vec<_> = LinesWithHashIterator::new(&mut *ours)
+		.map_err(to_error)?
+		.collect::<Result<_, _>>()
+		.map_err(to_error)?;
+	let theirs_lines: Vec<_> = LinesWithHashIterator::new(&mut *theirs)
+		.map_err(to_error)?
+		.collect::<Result<_, _>>()
+		.map_err(to_error)?;
+	let ours_map: HashMap<_, _> = ours_lines
+		.iter()
+		.map(|(label, start, size, hash)| (label.clone(), (*start, *size, *hash)))
+		.collect();
+	let theirs_map: HashMap<_, _> = theirs_lines
+		.iter()
+		.map(|(label, start, size, hash)| (label.clone(), (*start, *size, *hash)))
+		.collect();
+
+	let mut base_data = |label: &str| -> IOResult<Option<Vec<u8>>> {
+		match base_lines.get(label) {
+			Some(&(start, size, _)) => read_block(&mut *base, start, size).map(Some),
+			None => Ok(None),
+		}
+	};
+	ours.seek(SeekFrom::Start(0))?;
+	let ours_slice = ReadSlice::new(&mut *ours)?;
+	theirs.seek(SeekFrom::Start(0))?;
+	let theirs_slice = ReadSlice::new(&mut *theirs)?;
+	let mut resolve = |label: &str,
+	                   ours: Option<(u64, u64)>,
+	                   theirs: Option<(u64, u64)>|
+	 -> IOResult<Option<Choice>> {
+		let conflict = LabelConflict {
+			label: label.to_string(),
+			base: base_data(label)?,
+			ours: match ours {
+				Some((start, size)) => Some(read_all(&ours_slice.range(start, size))?),
+				None => None,
+			},
+			theirs: match theirs {
+				Some((start, size)) => Some(read_all(&theirs_slice.range(start, size))?),
+				None => None,
+			},
+		};
+		Ok(match resolver(&conflict) {
+			Resolution::Ours => ours.map(|(start, size)| Choice::Ours(start, size)),
+			Resolution::Theirs => theirs.map(|(start, size)| Choice::Theirs(start, size)),
+			Resolution::Base => conflict.base.map(Choice::Data),
+			Resolution::Data(data) => Some(Choice::Data(data)),
+			Resolution::Remove => None,
+		})
+	};
+
+	let mut merged: Vec<(String, Choice)> = vec![];
+	for (label, start, size, hash) in &ours_lines {
+		let ours = Some((*start, *size));
+		let choice = match (base_lines.get(label), theirs_map.get(label)) {
+			(Some(&(_, _, b)), Some(&(ts, tz, t))) => {
+				if *hash == b {
+					Some(Choice::Theirs(ts, tz))
+				} else if t == b || t == *hash {
+					Some(Choice::Ours(*start, *size))
+				} else {
+					resolve(label, ours, Some((ts, tz)))?
+				}
+			}
+			(Some(&(_, _, b)), None) => {
+				if *hash == b {
+					None
+				} else {
+					resolve(label, ours, None)?
+				}
+			}
+			(None, Some(&(ts, tz, t))) => {
+				if t == *hash {
+					Some(Choice::Ours(*start, *size))
+				} else {
+					resolve(label, ours, Some((ts, tz)))?
+				}
+			}
+			(None, None) => Some(Choice::Ours(*start, *size)),
+		};
+		if let Some(choice) = choice {
+			merged.push((label.clone(), choice));
+		}
+	}
+
+	let positions: HashMap<&String, usize> = merged
+		.iter()
+		.enumerate()
+		.map(|(index, (label, _))| (label, index))
+		.collect();
+	let mut added: HashMap<Place, Vec<(String, Choice)>> = HashMap::new();
+	let mut place = Place::Start;
+	for (label, start, size, hash) in &theirs_lines {
+		if let Some(&index) = positions.get(label) {
+			place = Place::After(index);
+		} else if !ours_map.contains_key(label) {
+			let choice = match base_lines.get(label) {
+				Some(&(_, _, b)) if b == *hash => None,
+				Some(_) => resolve(label, None, Some((*start, *size)))?,
+				None => Some(Choice::Theirs(*start, *size)),
+			};
+			if let Some(choice) = choice {
+				added
+					.entry(place)
+					.or_default()
+					.push((label.clone(), choice));
+			}
+		}
+	}
+	let mut ordered = added.remove(&Place::Start).unwrap_or_default();
+	for (index, item) in merged.into_iter().enumerate() {
+		ordered.push(item);
+		ordered.extend(added.remove(&Place::After(index)).into_iter().flatten());
+	}
+
+	let mut parts = vec![];
+	let mut indexes = Indexes::new();
+	let mut position = 0;
+	for (label, choice) in ordered {
+		let data = match choice {
+			Choice::Ours(start, size) => ours_slice.range(start, size),
+			Choice::Theirs(start, size) => theirs_slice.range(start, size),
+			Choice::Data(data) => ReadSlice::from(data),
+		};
+		indexes.insert(label, position, data.size());
+		position += data.size();
+		parts.push(data);
+	}
+	let file = ReadSlice::concat(parts);
+	create_diff(base, &mut IndexedFile::new(file, indexes), output)
+}
+
+fn read_block<R: Read + Seek>(input: &mut R, start: u64, size: u64) -> IOResult<Vec<u8>> {
+	let mut out = vec![];
+	input.seek(SeekFrom::Start(start))?;
+	input.take(size).read_to_end(&mut out)?;
+	Ok(out)
+}
+
+#[cfg(test)]
+mod merge_tests {
+	use super::{
+		merge_diffs, merge_labeled, rebase_diff, Conflict, LabelConflict, MergeResult, Resolution,
+	};
+	use diff::apply_diff;
+	use diff_block::DiffBlock;
+	use diff_writer::DiffWriter;
+	use indexes::{IndexedFile, Indexes};
+	use readslice::ReadSlice;
+	use std::io::Cursor;
+
+	fn edits(edits: &[(u64, u64, &[u8])], size: u64) -> Cursor<Vec<u8>> {
+		let mut writer = DiffWriter::new(vec![]);
+		for (start, remove, data) in edits {
+			writer
+				.replace(*start, *remove, ReadSlice::from(data.to_vec()))
+				.unwrap();
+		}
+		Cursor::new(writer.finish_with_source_size(size).unwrap())
+	}
+
+	fn apply(base: &[u8], diff: Vec<u8>) -> Vec<u8> {
+		let mut out = vec![];
+		apply_diff(
+			&mut Cursor::new(base.to_vec()),
+			&mut Cursor::new(diff),
+			&mut out,
+		)
+		.unwrap();
+		out
+	}
+
+	#[test]
+	fn works_test() {
+		let base = b"0123456789";
+		let ours = edits(&[(1, 2, b"ab"), (5, 0, b"++")], 10);
+		let theirs = edits(&[(5, 1, b"X"), (9, 1, b"")], 10);
+		let mut out = vec![];
+		let result = merge_diffs(ours, theirs, &mut out).unwrap();
+		assert_eq!(result, MergeResult::Merged);
+		assert_eq!(apply(base, out), b"0ab34++X678");
+	}
+
+	#[test]
+	fn same_edits_test() {
+		let base = b"0123456789";
+		let ours = edits(&[(1, 2, b"ab")], 10);
+		let theirs = edits(&[(1, 2, b"ab"), (7, 0, b"!")], 10);
+		let mut out = vec![];
+		let result = merge_diffs(ours, theirs, &mut out).unwrap();
+		assert_eq!(result, MergeResult::Merged);
+		assert_eq!(apply(base, out), b"0ab3456!789");
+	}
+
+	#[test]
+	fn truncation_test() {
+		let base = b"0123456789";
+		let ours = edits(&[(1, 1, b"a")], 10);
+		let theirs = edits(&[], 6);
+		let mut out = vec![];
+		let result = merge_diffs(ours, theirs, &mut out).unwrap();
+		assert_eq!(result, MergeResult::Merged);
+		assert_eq!(apply(base, out), b"0a2345");
+	}
+
+	#[test]
+	fn conflicts_test() {
+		let ours = edits(&[(1, 3, b"abc"), (6, 0, b"+")], 10);
+		let theirs = edits(&[(3, 1, b"X"), (5, 2, b"")], 10);
+		let mut out = vec![];
+		let result = merge_diffs(ours, theirs, &mut out).unwrap();
+		assert_eq!(
+			result,
+			MergeResult::Conflicts(vec![
+				Conflict {
+					ours: 1..4,
+					theirs: 3..4,
+				},
+				Conflict {
+					ours: 6..6,
+					theirs: 5..7,
+				},
+			])
+		);
+		assert!(out.is_empty());
+	}
+
+	#[test]
+	fn empty_blocks_test() {
+		let base = b"0123456789";
+		let mut writer = DiffWriter::new(vec![]);
+		let blocks = vec![
+			DiffBlock::Skip { size: 5 },
+			DiffBlock::Add {
+				data: ReadSlice::from(vec![]),
+			},
+			DiffBlock::Remove { size: 0 },
+			DiffBlock::Skip { size: 5 },
+		];
+		writer.write_blocks(blocks).unwrap();
+		let ours = Cursor::new(writer.finish().unwrap());
+		let theirs = edits(&[(4, 2, b"X")], 10);
+		let mut out = vec![];
+		let result = merge_diffs(ours, theirs, &mut out).unwrap();
+		assert_eq!(result, MergeResult::Merged);
+		assert_eq!(apply(base, out), b"0123X6789");
+	}
+
+	fn labeled(records: &[(&str, &[u8])]) -> IndexedFile<Cursor<Vec<u8>>> {
+		let mut data = vec![];
+		let mut indexes = Indexes::new();
+		for (label, record) in records {
+			indexes.insert(label.to_string(), data.len() as u64, record.len() as u64);
+			data.extend_from_slice(record);
+		}
+		IndexedFile::new(Cursor::new(data), indexes)
+	}
+
+	#[test]
+	fn labeled_test() {
+		let mut base = labeled(&[("a", b"AAA"), ("b", b"BBB"), ("c", b"CCC")]);
+		let mut ours = labeled(&[("a", b"A1"), ("b", b"BBB")]);
+		let mut theirs = labeled(&[("a", b"AAA"), ("b", b"B2"), ("d", b"DDDD"), ("c", b"CCC")]);
+		let mut out = vec![];
+		merge_labeled(&mut base, &mut ours, &mut theirs, &mut out, |_| {
+			panic!("No conflicts expected")
+		})
+		.unwrap();
+		assert_eq!(apply(b"AAABBBCCC", out), b"A1B2DDDD");
+	}
+
+	#[test]
+	fn labeled_order_test() {
+		let mut base = labeled(&[("a", b"A"), ("b", b"B"), ("c", b"C")]);
+		let mut ours = labeled(&[("a", b"A"), ("c", b"C")]);
+		let mut theirs = labeled(&[
+			("s1", b"1"),
+			("s2", b"2"),
+			("a", b"A"),
+			("b", b"B"),
+			("x", b"x"),
+			("y", b"y"),
+			("c", b"C"),
+			("z", b"z"),
+		]);
+		let mut out = vec![];
+		merge_labeled(&mut base, &mut ours, &mut theirs, &mut out, |_| {
+			panic!("No conflicts expected")
+		})
+		.unwrap();
+		assert_eq!(apply(b"ABC", out), b"12AxyCz");
+	}
+
+	#[test]
+	fn labeled_conflict_test() {
+		let mut base = labeled(&[("a", b"AAA"), ("b", b"BBB")]);
+		let mut ours = labeled(&[("a", b"A1"), ("b", b"B1")]);
+		let mut theirs = labeled(&[("a", b"A2")]);
+		let mut conflicts = vec![];
+		let mut out = vec![];
+		merge_labeled(&mut base, &mut ours, &mut theirs, &mut out, |conflict| {
+			conflicts.push(conflict.clone());
+			match conflict.label.as_str() {
+				"a" => Resolution::Data(b"A12".to_vec()),
+				_ => Resolution::Ours,
+			}
+		})
+		.unwrap();
+		assert_eq!(
+			conflicts,
+			vec![
+				LabelConflict {
+					label: "a".to_string(),
+					base: Some(b"AAA".to_vec()),
+					ours: Some(b"A1".to_vec()),
+					theirs: Some(b"A2".to_vec()),
+				},
+				LabelConflict {
+					label: "b".to_string(),
+					base: Some(b"BBB".to_vec()),
+					ours: Some(b"B1".to_vec()),
+					theirs: None,
+				},
+			]
+		);
+		assert_eq!(apply(b"AAABBB", out), b"A12B1");
+	}
+
+	#[test]
+	fn rebase_test() {
+		let base = b"0123456789";
+		let diff = edits(&[(1, 2, b"ab"), (5, 0, b"++"), (8, 1, b"")], 10);
+		let onto = edits(&[(0, 1, b"zero"), (5, 1, b"X"), (8, 1, b"")], 10);
+		let mut rebased = vec![];
+		let mut c = vec![];
+		apply_diff(&mut Cursor::new(base.to_vec()), &mut onto.clone(), &mut c).unwrap();
+		assert_eq!(c, b"zero1234X679");
+
+		let result = rebase_diff(diff.clone(), onto.clone(), &mut rebased).unwrap();
+		assert_eq!(result, MergeResult::Merged);
+		assert_eq!(apply(&c, rebased), b"zeroab34++X679");
+
+		let conflicting = edits(&[(0, 2, b"")], 10);
+		let mut out = vec![];
+		let result = rebase_diff(conflicting, onto, &mut out).unwrap();
+		assert_eq!(
+			result,
+			MergeResult::Conflicts(vec![Conflict {
+				ours: 0..2,
+				theirs: 0..1,
+			}])
+		);
+	}
+}
@@ -0,0 +1,156 @@
+//! Contains `MmapFile`
+//!
+//! Module is available with `mmap` feature
+
+use memmap2::Mmap;
+use readat::ReadAt;
+use readslice::ReadSlice;
+use std::cmp::min;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Memory-mapped file
+///
+/// Implements `Read` and `Seek` without any syscalls, so it can be used as a cheap source
+/// for `WithIndexes` implementations, `apply_diff` and `ReadSlice`.
+/// Clones share the same mapping, but have independent positions,
+/// which makes it suitable for `create_diff_parallel` as well.
+#[derive(Clone)]
+pub struct MmapFile {
+	map: Arc<Mmap>,
+	position: u64,
+}
+
+impl MmapFile {
+	/// Maps file at the path into memory
+	///
+	/// # Safety
+	///
+	/// Behavior is undefined if the file is modified or truncated while it is mapped,
+	/// either by this or by another process
+	pub unsafe fn open<P: AsRef<Path>>(path: P) -> IOResult<Self> {
+		let file = File::open(path)?;
+		Ok(Self::from_mmap(Mmap::map(&file)?))
+	}
+
+	/// Creates `MmapFile` from existing mapping
+	pub fn from_mmap(map: Mmap) -> Self {
+		Self {
+			map: Arc::new(map),
+			position: 0,
+		}
+	}
+
+	/// Returns whole mapped file as a byte slice
+	pub fn as_slice(&self) -> &[u8] {
+		&self.map
+	}
+
+	/// Returns length of the mapped file
+	pub fn len(&self) -> u64 {
+		self.map.len() as u64
+	}
+
+	/// Checks if mapped file is empty
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+}
+
+impl AsRef<[u8]> for MmapFile {
+	fn as_ref(&self) -> &[u8] {
+		self.as_slice()
+	}
+}
+
+impl Read for MmapFile {
+	fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+		let start = min(self.position, self.len()) as usize;
+		let read = (&self.map[start..]).read(buffer)?;
+		self.position += read as u64;
+		Ok(read)
+	}
+}
+
+impl Seek for MmapFile {
+	fn seek(&mut self, from: SeekFrom) -> IOResult<u64> {
+		let pos = match from {
+			SeekFrom::Start(x) => Some(x),
+			SeekFrom::End(x) => self.len().checked_add_signed(x),
+			SeekFrom::Current(x) => self.position.checked_add_signed(x),
+		};
+		match pos {
+			Some(pos) => {
+				self.position = pos;
+				Ok(pos)
+			}
+			None => Err(Error::new(
+				ErrorKind::InvalidInput,
+				"Invalid seek to a negative position",
+			)),
+		}
+	}
+}
+
+impl ReadAt for MmapFile {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		self.as_slice().read_at(buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		Ok(self.len())
+	}
+}
+
+impl<'a> From<MmapFile> for ReadSlice<'a> {
+	fn from(v: MmapFile) -> Self {
+		let position = v.position;
+		ReadSlice::from_bytes(v).offset(position)
+	}
+}
+
+#[cfg(test)]
+mod mmap_tests {
+	use super::MmapFile;
+	use diff::{apply_diff, create_diff};
+	use readslice::ReadSlice;
+	use std::fs::read;
+	use std::io::{Cursor, Read, Seek, SeekFrom};
+	use test_mod::TextFile;
+
+	#[test]
+	fn read_test() {
+		let mut file = unsafe { MmapFile::open("./test_data/a_a.txt").unwrap() };
+		let contents = read("./test_data/a_a.txt").unwrap();
+		assert_eq!(file.as_slice(), &contents[..]);
+
+		file.seek(SeekFrom::Start(10)).unwrap();
+		let mut slice = ReadSlice::from(file.clone());
+		let mut buf = vec![];
+		slice.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, &contents[10..]);
+
+		let mut buf = vec![];
+		file.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, &contents[10..]);
+	}
+
+	#[test]
+	fn apply_diff_test() {
+		let mut diff = Cursor::new(vec![]);
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		diff.seek(SeekFrom::Start(0)).unwrap();
+
+		let mut file = unsafe { MmapFile::open("./test_data/a_a.txt").unwrap() };
+		let mut restored = vec![];
+		apply_diff(&mut file, &mut diff, &mut restored).unwrap();
+		assert_eq!(restored, read("./test_data/a_b.txt").unwrap());
+	}
+}
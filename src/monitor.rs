@@ -0,0 +1,130 @@
+//! Contains `Monitor` tracking progress of the observed operations
+
+use indexes::{Indexes, WithIndexes};
+use progress::{CancellationToken, Observer, Phase, Progress};
+use std::io::{Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Number of read or written bytes reported to the observer at once
+const REPORT_INTERVAL: u64 = 1024 * 1024;
+
+/// Shared state of the observed operation
+///
+/// Bytes are counted without notifying the observer, pending counts are reported with
+/// the next `update` or once they reach `REPORT_INTERVAL`
+pub struct Monitor<'a> {
+	state: Mutex<(&'a mut dyn Observer, Progress)>,
+	hashed: AtomicU64,
+	written: AtomicU64,
+	token: &'a CancellationToken,
+}
+
+impl<'a> Monitor<'a> {
+	pub fn new(observer: &'a mut dyn Observer, token: &'a CancellationToken, phase: Phase) -> Self {
+		Self {
+			state: Mutex::new((
+				observer,
+				Progress {
+					phase,
+					bytes_hashed: 0,
+					blocks: 0,
+					bytes_written: 0,
+				},
+			)),
+			hashed: AtomicU64::new(0),
+			written: AtomicU64::new(0),
+			token,
+		}
+	}
+
+	/// Fails if operation is cancelled
+	pub fn check(&self) -> IOResult<()> {
+		if self.token.is_cancelled() {
+			return Err(Error::new(ErrorKind::Interrupted, "Operation cancelled"));
+		}
+		Ok(())
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.token.is_cancelled()
+	}
+
+	/// Counts hashed bytes, ignored out of hashing phase
+	pub fn hashed(&self, size: u64) {
+		if self.hashed.fetch_add(size, Ordering::Relaxed) + size >= REPORT_INTERVAL {
+			self.update(|_| {});
+		}
+	}
+
+	/// Counts written bytes
+	pub fn written(&self, size: u64) {
+		if self.written.fetch_add(size, Ordering::Relaxed) + size >= REPORT_INTERVAL {
+			self.update(|_| {});
+		}
+	}
+
+	/// Adds pending counts, changes progress and reports it
+	pub fn update<F: FnOnce(&mut Progress)>(&self, change: F) {
+		let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+		let (ref mut observer, ref mut progress) = *state;
+		let hashed = self.hashed.swap(0, Ordering::Relaxed);
+		if progress.phase == Phase::Hashing {
+			progress.bytes_hashed += hashed;
+		}
+		progress.bytes_written += self.written.swap(0, Ordering::Relaxed);
+		change(progress);
+		observer.progress(progress);
+	}
+}
+
+/// Input counting bytes read while hashing
+///
+/// Reads as empty once operation is cancelled, so hashing finishes quickly
+pub struct ObservedFile<'a, 'b: 'a, T: WithIndexes> {
+	pub file: T,
+	pub monitor: &'a Monitor<'b>,
+}
+
+impl<'a, 'b: 'a, T: WithIndexes> Read for ObservedFile<'a, 'b, T> {
+	fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+		if self.monitor.is_cancelled() {
+			return Ok(0);
+		}
+		let read = self.file.read(buf)?;
+		self.monitor.hashed(read as u64);
+		Ok(read)
+	}
+}
+
+impl<'a, 'b: 'a, T: WithIndexes> Seek for ObservedFile<'a, 'b, T> {
+	fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+		self.file.seek(pos)
+	}
+}
+
+impl<'a, 'b: 'a, T: WithIndexes> WithIndexes for ObservedFile<'a, 'b, T> {
+	fn get_indexes(&mut self) -> Result<Indexes, String> {
+		self.file.get_indexes()
+	}
+}
+
+/// Output reporting written bytes
+pub struct ObservedWriter<'a, 'b: 'a, W: Write> {
+	pub output: W,
+	pub monitor: &'a Monitor<'b>,
+}
+
+impl<'a, 'b: 'a, W: Write> Write for ObservedWriter<'a, 'b, W> {
+	fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+		let written = self.output.write(buf)?;
+		self.monitor.written(written as u64);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> IOResult<()> {
+		self.output.flush()?;
+		self.monitor.update(|_| {});
+		Ok(())
+	}
+}
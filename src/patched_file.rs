@@ -0,0 +1,345 @@
+//! Contains `PatchedFile`
+//!
+//! Presents result of applying diff to the source without writing it out, bytes are
+//! read from the source or the diff only when requested.
+//!
+//! If the source implements `WithIndexes`, so does the patched file, with source indexes
+//! shifted to the target offsets. This allows to diff patched file without applying diff.
+
+use diff_index::DiffIndex;
+use functions::{read_n, vec_to_u32_be};
+use indexes::{Indexes, WithIndexes};
+use std::cmp::min;
+use std::io::{Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom};
+
+/// Diff block containing current position
+struct Block {
+	start: u64,
+	end: u64,
+	from_source: bool,
+	data_offset: u64,
+}
+
+/// Target of the diff readable at any offset
+///
+/// Uses `DiffIndex` to find block containing requested offset, so seeking doesn't
+/// require reading the diff from the start
+///
+/// ```
+/// use bin_diff::patched_file::PatchedFile;
+/// use std::io::{Cursor, Read, Seek, SeekFrom};
+///
+/// let source = Cursor::new(b"hello world".to_vec());
+/// #[cfg_attr(rustfmt, rustfmt_skip)]
+/// let diff = Cursor::new(vec![
+///     0, 0, 0, 0, 0, 6, // skip 6
+///     0, 4, 0, 0, 0, 5, b't', b'h', b'e', b'r', b'e', // replace 5 with same length
+/// ]);
+/// let mut file = PatchedFile::new(source, diff).unwrap();
+/// file.seek(SeekFrom::Start(4)).unwrap();
+/// let mut buf = String::new();
+/// file.read_to_string(&mut buf).unwrap();
+/// assert_eq!(buf, "o there");
+/// ```
+pub struct PatchedFile<S: Read + Seek, D: Read + Seek> {
+	source: S,
+	diff: D,
+	index: DiffIndex,
+	position: u64,
+	block: Option<Block>,
+}
+
+impl<S: Read + Seek, D: Read + Seek> PatchedFile<S, D> {
+	/// Creates file building index of the diff
+	pub fn new(source: S, mut diff: D) -> IOResult<Self> {
+		diff.seek(SeekFrom::Start(0))?;
+		let index = DiffIndex::from_diff(&mut diff)?;
+		Ok(Self::with_index(source, diff, index))
+	}
+
+	/// Creates file using already built index of the diff
+	pub fn with_index(source: S, diff: D, index: DiffIndex) -> Self {
+		Self {
+			source,
+			diff,
+			index,
+			position: 0,
+			block: None,
+		}
+	}
+
+	/// Returns index of the diff
+	pub fn index(&self) -> &DiffIndex {
+		&self.index
+	}
+
+	/// Returns size of the file
+	pub fn size(&self) -> u64 {
+		self.index.target_size()
+	}
+
+	/// Returns source and diff
+	pub fn into_inner(self) -> (S, D) {
+		(self.source, self.diff)
+	}
+
+	/// Returns `(source_offset, target_offset, size)` of the blocks copied from the source
+	fn source_ranges(&mut self) -> IOResult<Vec<(u64, u64, u64)>> {
+		let mut ranges = vec![];
+		let mut buf = [0u8; 2];
+		for (position, entry) in self.index.entries().iter().enumerate() {
+			self.diff.seek(SeekFrom::Start(entry.diff_offset))?;
+			read_n(&mut self.diff, &mut buf, 2)?;
+			if vec_to_u32_be(&buf) == 0 {
+				let size = self.index.entry_end(position) - entry.target_offset;
+				ranges.push((entry.source_offset, entry.target_offset, size));
+			}
+		}
+		Ok(ranges)
+	}
+
+	fn load_block(&mut self) -> IOResult<()> {
+		if let Some(ref block) = self.block {
+			if block.start <= self.position && self.position < block.end {
+				return Ok(());
+			}
+		}
+		let position = match self.index.find(self.position) {
+			Some(x) => x,
+			None => {
+				self.block = None;
+				return Ok(());
+			}
+		};
+		let entry = self.index.entries()[position];
+		let mut buf = [0u8; 2];
+		self.diff.seek(SeekFrom::Start(entry.diff_offset))?;
+		read_n(&mut self.diff, &mut buf, 2)?;
+		let (from_source, data_offset) = match vec_to_u32_be(&buf) {
+			0 => (true, entry.source_offset),
+			1 | 4 => (false, entry.diff_offset + 6),
+			3 => (false, entry.diff_offset + 10),
+			_ => {
+				return Err(Error::new(
+					ErrorKind::InvalidData,
+					"Diff doesn't match its index",
+				))
+			}
+		};
+		self.block = Some(Block {
+			start: entry.target_offset,
+			end: self.index.entry_end(position),
+			from_source,
+			data_offset,
+		});
+		Ok(())
+	}
+}
+
+impl<S: Read + Seek, D: Read + Seek> Read for PatchedFile<S, D> {
+	fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+		self.load_block()?;
+		let block = match self.block {
+			Some(ref block) => block,
+			None => return Ok(0),
+		};
+		let size = min(buf.len() as u64, block.end - self.position) as usize;
+		let offset = block.data_offset + self.position - block.start;
+		let input: &mut dyn Read = if block.from_source {
+			self.source.seek(SeekFrom::Start(offset))?;
+			&mut self.source
+		} else {
+			self.diff.seek(SeekFrom::Start(offset))?;
+			&mut self.diff
+		};
+		let read = input.read(&mut buf[..size])?;
+		if read == 0 && size > 0 {
+			return Err(Error::new(
+				ErrorKind::UnexpectedEof,
+				"Source or diff is shorter than the index expects",
+			));
+		}
+		self.position += read as u64;
+		Ok(read)
+	}
+}
+
+impl<S: Read + Seek, D: Read + Seek> Seek for PatchedFile<S, D> {
+	fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+		let (base, offset) = match pos {
+			SeekFrom::Start(x) => {
+				self.position = x;
+				return Ok(x);
+			}
+			SeekFrom::Current(x) => (self.position, x),
+			SeekFrom::End(x) => (self.size(), x),
+		};
+		let position = if offset < 0 {
+			base.checked_sub(offset.unsigned_abs())
+		} else {
+			base.checked_add(offset as u64)
+		};
+		match position {
+			Some(x) => {
+				self.position = x;
+				Ok(x)
+			}
+			None => Err(Error::new(
+				ErrorKind::InvalidInput,
+				"Invalid seek to a negative or overflowing position",
+			)),
+		}
+	}
+}
+
+/// Maps source offset to the target
+///
+/// Offsets inside changed source parts are moved to the start of the change,
+/// offsets at or beyond the end of the source covered by the diff to the end of the target
+fn map_offset(ranges: &[(u64, u64, u64)], sizes: (u64, u64), offset: u64) -> u64 {
+	let (source_size, target_size) = sizes;
+	if offset >= source_size {
+		return target_size;
+	}
+	let position = ranges.partition_point(|&(start, _, size)| start + size <= offset);
+	match ranges.get(position) {
+		Some(&(start, target, _)) if start <= offset => target + offset - start,
+		_ => match position.checked_sub(1).map(|x| ranges[x]) {
+			Some((_, target, size)) => target + size,
+			None => 0,
+		},
+	}
+}
+
+impl<S: WithIndexes, D: Read + Seek> WithIndexes for PatchedFile<S, D> {
+	/// Returns source indexes shifted to the target
+	///
+	/// Changed bytes are attributed to one of the items touching the change, so items
+	/// covering the whole source still cover the whole target. Items which become empty are omitted
+	fn get_indexes(&mut self) -> Result<Indexes, String> {
+		let indexes = self.source.get_indexes()?;
+		let ranges = self
+			.source_ranges()
+			.map_err(|e| format!("Cannot read diff: {}", e))?;
+		let sizes = (self.index.source_size(), self.size());
+		let mut out = Indexes::new();
+		for (label, start, size) in indexes {
+			let new_start = map_offset(&ranges, sizes, start);
+			let new_end = map_offset(&ranges, sizes, start + size);
+			if new_end > new_start {
+				out.insert(label, new_start, new_end - new_start);
+			}
+		}
+		Ok(out)
+	}
+}
+
+#[cfg(test)]
+mod patched_file_tests {
+	use super::PatchedFile;
+	use diff::{apply_diff, create_diff, create_diff_with_index};
+	use diff_writer::DiffWriter;
+	use indexes::WithIndexes;
+	use readslice::ReadSlice;
+	use std::fs::{read, File};
+	use std::io::{Cursor, Read, Seek, SeekFrom};
+	use test_mod::TextFile;
+
+	#[test]
+	fn works_test() {
+		let pairs = [
+			["./test_data/a_a.txt", "./test_data/a_b.txt"],
+			["./test_data/a_c.txt", "./test_data/a_d.txt"],
+		];
+		for pair in pairs.iter() {
+			let mut diff = vec![];
+			let index = create_diff_with_index(
+				&mut TextFile::from_path(pair[0]),
+				&mut TextFile::from_path(pair[1]),
+				&mut diff,
+			)
+			.unwrap();
+			let expected = read(pair[1]).unwrap();
+			let source = File::open(pair[0]).unwrap();
+			let mut file = PatchedFile::with_index(source, Cursor::new(diff), index);
+			assert_eq!(file.size(), expected.len() as u64);
+
+			let mut result = vec![];
+			file.read_to_end(&mut result).unwrap();
+			assert_eq!(result, expected);
+
+			let size = expected.len();
+			for &(start, len) in [(0, 10), (size / 3, 100), (size / 2, 7), (size - 5, 20)].iter() {
+				file.seek(SeekFrom::Start(start as u64)).unwrap();
+				let mut buf = vec![];
+				file.by_ref()
+					.take(len as u64)
+					.read_to_end(&mut buf)
+					.unwrap();
+				assert_eq!(buf[..], expected[start..size.min(start + len)]);
+			}
+
+			assert_eq!(file.seek(SeekFrom::End(-3)).unwrap(), size as u64 - 3);
+			assert_eq!(file.seek(SeekFrom::Current(1)).unwrap(), size as u64 - 2);
+			assert!(file.seek(SeekFrom::Current(-(size as i64))).is_err());
+		}
+	}
+
+	#[test]
+	fn indexes_test() {
+		let mut writer = DiffWriter::new(vec![]);
+		writer
+			.replace(5, 4, ReadSlice::from(b"xx".to_vec()))
+			.unwrap();
+		writer.remove(10, 5).unwrap();
+		writer
+			.insert(15, ReadSlice::from(b"new\n".to_vec()))
+			.unwrap();
+		let diff = writer.finish().unwrap();
+
+		let source = TextFile::new("aaaa\nbbbb\ncccc\ndddd\neeee\n".to_string());
+		let mut file = PatchedFile::new(source, Cursor::new(diff)).unwrap();
+		let mut result = String::new();
+		file.read_to_string(&mut result).unwrap();
+		assert_eq!(result, "aaaa\nxx\nnew\n");
+
+		let indexes: Vec<_> = file.get_indexes().unwrap().collect();
+		assert_eq!(
+			indexes,
+			[
+				("line_0".to_string(), 0, 5),
+				("line_1".to_string(), 5, 3),
+				("line_2".to_string(), 8, 4),
+			]
+		);
+	}
+
+	#[test]
+	fn create_diff_test() {
+		let mut diff_ab = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff_ab,
+		)
+		.unwrap();
+		let source = TextFile::from_path("./test_data/a_a.txt");
+		let mut file_b = PatchedFile::new(source, Cursor::new(diff_ab)).unwrap();
+
+		let mut diff_bc = vec![];
+		create_diff(
+			&mut file_b,
+			&mut TextFile::from_path("./test_data/a_c.txt"),
+			&mut diff_bc,
+		)
+		.unwrap();
+		let mut result = vec![];
+		apply_diff(
+			&mut File::open("./test_data/a_b.txt").unwrap(),
+			&mut Cursor::new(diff_bc),
+			&mut result,
+		)
+		.unwrap();
+		assert_eq!(result, read("./test_data/a_c.txt").unwrap());
+	}
+}
@@ -0,0 +1,69 @@
+//! Contains progress reporting and cancellation of long running operations
+//!
+//! Functions with `_observed` suffix in `diff` module report `Progress` to an `Observer`
+//! and check `CancellationToken` between blocks. Cancelled operation fails with
+//! `ErrorKind::Interrupted`, output is left partially written.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Stage of the operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+	/// Blocks of the inputs are hashed
+	Hashing,
+	/// Diffs are combined in memory
+	Combining,
+	/// Diff blocks are written
+	Writing,
+	/// Diff is applied
+	Applying,
+}
+
+/// Progress of the operation, counters are totals since the start
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+	/// Current stage of the operation
+	pub phase: Phase,
+	/// Number of bytes read for hashing
+	pub bytes_hashed: u64,
+	/// Number of processed blocks, while combining number of blocks of the combined diff so far
+	pub blocks: u64,
+	/// Number of bytes written to the output
+	pub bytes_written: u64,
+}
+
+/// Receives progress of the operation
+pub trait Observer {
+	/// Called after every block and after every megabyte read or written in between
+	fn progress(&mut self, progress: &Progress);
+}
+
+impl<F: FnMut(&Progress)> Observer for F {
+	fn progress(&mut self, progress: &Progress) {
+		self(progress)
+	}
+}
+
+/// Flag aborting operation, clones share the same flag
+///
+/// Can be cancelled from any thread
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+	/// Creates token which is not cancelled
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Requests cancellation
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::SeqCst);
+	}
+
+	/// Checks if cancellation was requested
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::SeqCst)
+	}
+}
@@ -0,0 +1,116 @@
+//! Contains `ReadAt` trait
+//!
+//! Positional reads don't move any shared cursor, so many readers can use the same source
+//! at once. `ReadSlice` uses them for sources implementing `ReadAt`.
+
+use std::cmp::min;
+#[cfg(any(unix, windows))]
+use std::fs::File;
+use std::io::Result as IOResult;
+use std::sync::Arc;
+
+/// Source which can be read at arbitrary offset without seeking
+pub trait ReadAt {
+	/// Reads bytes starting at `offset` into `buf`, returns number of read bytes
+	///
+	/// Returns `0` if `offset` is at or beyond the end of the source
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize>;
+
+	/// Returns size of the source in bytes
+	fn size(&self) -> IOResult<u64>;
+}
+
+impl ReadAt for [u8] {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		let start = min(offset, self.len() as u64) as usize;
+		let read = min(buf.len(), self.len() - start);
+		buf[..read].copy_from_slice(&self[start..start + read]);
+		Ok(read)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		Ok(self.len() as u64)
+	}
+}
+
+impl ReadAt for Vec<u8> {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		self[..].read_at(buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		Ok(self.len() as u64)
+	}
+}
+
+#[cfg(unix)]
+impl ReadAt for File {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		::std::os::unix::fs::FileExt::read_at(self, buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		Ok(self.metadata()?.len())
+	}
+}
+
+/// On Windows `seek_read` moves file cursor, so it should not be mixed with `Read` on the same handle
+#[cfg(windows)]
+impl ReadAt for File {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		::std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		Ok(self.metadata()?.len())
+	}
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		(**self).read_at(buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		(**self).size()
+	}
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		(**self).read_at(buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		(**self).size()
+	}
+}
+
+#[cfg(test)]
+mod readat_tests {
+	use super::ReadAt;
+	use std::fs::{read, File};
+
+	#[test]
+	fn bytes_test() {
+		let data = vec![1, 2, 3, 4, 5];
+		let mut buf = [0; 3];
+		assert_eq!(data.read_at(&mut buf, 1).unwrap(), 3);
+		assert_eq!(buf, [2, 3, 4]);
+		assert_eq!(data.read_at(&mut buf, 4).unwrap(), 1);
+		assert_eq!(buf[0], 5);
+		assert_eq!(data.read_at(&mut buf, 10).unwrap(), 0);
+		assert_eq!(data.size().unwrap(), 5);
+	}
+
+	#[cfg(any(unix, windows))]
+	#[test]
+	fn file_test() {
+		let contents = read("./test_data/a_a.txt").unwrap();
+		let file = File::open("./test_data/a_a.txt").unwrap();
+		let mut buf = [0; 4];
+		assert_eq!(file.read_at(&mut buf, 10).unwrap(), 4);
+		assert_eq!(buf, contents[10..14]);
+		assert_eq!(file.size().unwrap(), contents.len() as u64);
+	}
+}
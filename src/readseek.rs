@@ -1,5 +1,8 @@
+//! Contains `ReadSeek` trait
+
 use std::io::{Read, Seek};
 
+/// Shorthand for `Read + Seek`, implemented for every such type
 pub trait ReadSeek: Read + Seek {}
 
 impl<T: Read + Seek> ReadSeek for T {}
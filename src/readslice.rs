@@ -1,30 +1,101 @@
+//! Contains `ReadSlice`
+//!
+//! `ReadSlice` is a cheap readable view over a range of one or more sources.
+//! It is used to describe blocks of diffs and is handy for `WithIndexes` implementors
+//! to expose sub-ranges of their files.
+
+use indexes::Indexes;
+use readat::ReadAt;
 use readseek::ReadSeek;
 use std::cmp::min;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::fs::File;
-use std::io::{Cursor, Read, Result as IOResult, Seek, SeekFrom};
-use std::rc::Rc;
-use std::sync::Mutex;
+use std::io::{Cursor, Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// Kind of sources a `ReadSlice` is built from
+///
+/// `Local` slices accept any source, `Shared` ones only thread-safe sources and are `Send + Sync`
+pub trait Sharing {
+	/// Type of the sources shared through a lock
+	type Seek<'a>: ?Sized + ReadSeek + 'a;
+	/// Type of the positional sources
+	type At<'a>: ?Sized + ReadAt + 'a;
+}
+
+/// Default `Sharing` of `ReadSlice`, slices can't be moved across threads
+#[derive(Clone, Copy, Debug)]
+pub struct Local;
+
+/// `Sharing` of slices which can be moved across threads, see `SendReadSlice`
+#[derive(Clone, Copy, Debug)]
+pub struct Shared;
+
+impl Sharing for Local {
+	type Seek<'a> = dyn ReadSeek + 'a;
+	type At<'a> = dyn ReadAt + 'a;
+}
+
+impl Sharing for Shared {
+	type Seek<'a> = dyn ReadSeek + Send + 'a;
+	type At<'a> = dyn ReadAt + Send + Sync + 'a;
+}
+
+enum Source<'a, K: Sharing> {
+	Seek(Arc<Mutex<Box<K::Seek<'a>>>>),
+	At(Arc<K::At<'a>>),
+}
+
+impl<'a, K: Sharing> Clone for Source<'a, K> {
+	fn clone(&self) -> Self {
+		match self {
+			Source::Seek(v) => Source::Seek(v.clone()),
+			Source::At(v) => Source::At(v.clone()),
+		}
+	}
+}
 
-struct Slice<'a> {
-	v: Rc<Mutex<Box<dyn ReadSeek + 'a>>>,
+/// Adapts any byte container to `ReadAt`
+struct Bytes<T>(T);
+
+impl<T: AsRef<[u8]>> ReadAt for Bytes<T> {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> IOResult<usize> {
+		self.0.as_ref().read_at(buf, offset)
+	}
+
+	fn size(&self) -> IOResult<u64> {
+		Ok(self.0.as_ref().len() as u64)
+	}
+}
+
+struct Slice<'a, K: Sharing> {
+	v: Source<'a, K>,
 	size: u64,
 	initial_position: u64,
 	position: u64,
 }
 
-impl<'a> Slice<'a> {
-	fn new<T: 'a + ReadSeek>(mut v: T) -> Self {
-		let init_pos = v.seek(SeekFrom::Current(0)).unwrap();
-		let end = v.seek(SeekFrom::End(0)).unwrap();
-		let size = end - init_pos;
-		v.seek(SeekFrom::Start(init_pos)).unwrap();
-		Self {
-			v: Rc::new(Mutex::new(Box::new(v))),
+impl<'a, K: Sharing> Slice<'a, K> {
+	fn new(mut v: Box<K::Seek<'a>>) -> IOResult<Self> {
+		let init_pos = v.seek(SeekFrom::Current(0))?;
+		let end = v.seek(SeekFrom::End(0))?;
+		let size = end.saturating_sub(init_pos);
+		v.seek(SeekFrom::Start(init_pos))?;
+		Ok(Self {
+			v: Source::Seek(Arc::new(Mutex::new(v))),
 			size,
 			initial_position: init_pos,
 			position: 0,
+		})
+	}
+
+	fn from_read_at(v: Arc<K::At<'a>>, size: u64) -> Self {
+		Self {
+			v: Source::At(v),
+			size,
+			initial_position: 0,
+			position: 0,
 		}
 	}
 
@@ -57,7 +128,7 @@ impl<'a> Slice<'a> {
 	}
 }
 
-impl<'a> Debug for Slice<'a> {
+impl<'a, K: Sharing> Debug for Slice<'a, K> {
 	fn fmt(&self, f: &mut Formatter) -> FmtResult {
 		write!(
 			f,
@@ -67,7 +138,7 @@ impl<'a> Debug for Slice<'a> {
 	}
 }
 
-impl<'a> Clone for Slice<'a> {
+impl<'a, K: Sharing> Clone for Slice<'a, K> {
 	fn clone(&self) -> Self {
 		Self {
 			v: self.v.clone(),
@@ -78,34 +149,60 @@ impl<'a> Clone for Slice<'a> {
 	}
 }
 
-impl<'a> Read for Slice<'a> {
+impl<'a, K: Sharing> Read for Slice<'a, K> {
 	fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
-		let pos = self.position;
-		self.seek(SeekFrom::Start(pos))?;
-		let maxread = (self.size - self.position) as usize;
-		let end = min(maxread, buffer.len());
-		let read = self.v.lock().unwrap().read(&mut buffer[0..end])?;
+		let maxread = self.size.saturating_sub(self.position);
+		let end = min(maxread, buffer.len() as u64) as usize;
+		let pos = self.initial_position + self.position;
+		let read = match self.v {
+			Source::Seek(ref v) => {
+				let mut v = v.lock().unwrap();
+				v.seek(SeekFrom::Start(pos))?;
+				v.read(&mut buffer[0..end])?
+			}
+			Source::At(ref v) => v.read_at(&mut buffer[0..end], pos)?,
+		};
 		self.position += read as u64;
 		Ok(read)
 	}
 }
 
-impl<'a> Seek for Slice<'a> {
+impl<'a, K: Sharing> Seek for Slice<'a, K> {
 	fn seek(&mut self, from: SeekFrom) -> IOResult<u64> {
+		let v = match self.v {
+			Source::Seek(ref v) => v,
+			Source::At(ref v) => {
+				let pos = match from {
+					SeekFrom::Start(x) => Some(x),
+					SeekFrom::End(x) => v
+						.size()?
+						.checked_add_signed(x)
+						.and_then(|x| x.checked_sub(self.initial_position)),
+					SeekFrom::Current(x) => self.position.checked_add_signed(x),
+				};
+				return match pos {
+					Some(pos) => {
+						self.position = pos;
+						Ok(pos)
+					}
+					None => Err(negative_seek()),
+				};
+			}
+		};
 		match from {
 			SeekFrom::Start(x) => {
 				let pos = self.initial_position + x;
-				let seek = self.v.lock().unwrap().seek(SeekFrom::Start(pos))?;
+				let seek = v.lock().unwrap().seek(SeekFrom::Start(pos))?;
 				self.position = seek - self.initial_position;
 				Ok(self.position)
 			}
 			SeekFrom::End(x) => {
-				let seek = self.v.lock().unwrap().seek(SeekFrom::End(x))?;
+				let seek = v.lock().unwrap().seek(SeekFrom::End(x))?;
 				self.position = seek - self.initial_position;
 				Ok(self.position)
 			}
 			SeekFrom::Current(x) => {
-				let seek = self.v.lock().unwrap().seek(SeekFrom::Current(x))?;
+				let seek = v.lock().unwrap().seek(SeekFrom::Current(x))?;
 				self.position = seek - self.initial_position;
 				Ok(self.position)
 			}
@@ -113,12 +210,35 @@ impl<'a> Seek for Slice<'a> {
 	}
 }
 
-pub struct ReadSlice<'a> {
-	slices: Vec<Slice<'a>>,
+fn negative_seek() -> Error {
+	Error::new(
+		ErrorKind::InvalidInput,
+		"Invalid seek to a negative position",
+	)
+}
+
+/// Readable and seekable view over a range of one or more sources
+///
+/// Positions are relative to the start of the view: seeking to `0` moves to the first byte of the range,
+/// reads stop at its end. `offset`, `take` and `chain` don't modify the view, they return new one
+/// sharing the same sources. Each view has its own position, so clones can be read independently.
+///
+/// Sources created with `new` and `chain` are shared through a lock and seeked before every read,
+/// positional sources (`from_bytes`, `from_read_at`, `from_file`, `From` conversions for bytes and `Cursor`)
+/// are read without any shared cursor, which is cheaper.
+///
+/// Slices are `Local` by default, see `SendReadSlice` for slices usable from other threads
+pub struct ReadSlice<'a, K: Sharing = Local> {
+	slices: Vec<Slice<'a, K>>,
 	size: u64,
 }
 
-impl<'a> Debug for ReadSlice<'a> {
+/// `ReadSlice` over thread-safe sources, which is `Send + Sync`
+///
+/// Created with `_send` counterparts of `ReadSlice` constructors
+pub type SendReadSlice<'a> = ReadSlice<'a, Shared>;
+
+impl<'a, K: Sharing> Debug for ReadSlice<'a, K> {
 	fn fmt(&self, f: &mut Formatter) -> FmtResult {
 		write!(
 			f,
@@ -129,8 +249,86 @@ impl<'a> Debug for ReadSlice<'a> {
 }
 
 impl<'a> ReadSlice<'a> {
-	pub fn new<T: 'a + ReadSeek>(v: T) -> Self {
-		let slice = Slice::new(v);
+	/// Creates `ReadSlice` from current position of the reader to its end
+	///
+	/// Fails if the reader can't be seeked to find its size
+	pub fn new<T: 'a + ReadSeek>(v: T) -> IOResult<Self> {
+		let v: Box<dyn ReadSeek + 'a> = Box::new(v);
+		Ok(Self::from_slice(Slice::new(v)?))
+	}
+
+	/// Creates `ReadSlice` over in-memory bytes
+	///
+	/// Unlike `new`, reads are served directly from the bytes without seeking of the shared reader
+	pub fn from_bytes<T: 'a + AsRef<[u8]>>(v: T) -> Self {
+		let size = v.as_ref().len() as u64;
+		let v: Arc<dyn ReadAt + 'a> = Arc::new(Bytes(v));
+		Self::from_slice(Slice::from_read_at(v, size))
+	}
+
+	/// Creates `ReadSlice` over positional reads source
+	///
+	/// Slices and their clones don't share any cursor, so they can be read in any order
+	pub fn from_read_at<T: 'a + ReadAt>(v: T) -> IOResult<Self> {
+		let size = v.size()?;
+		let v: Arc<dyn ReadAt + 'a> = Arc::new(v);
+		Ok(Self::from_slice(Slice::from_read_at(v, size)))
+	}
+
+	/// Creates `ReadSlice` from current position of the file to its end
+	///
+	/// Unlike `new`, the file is read with positional reads where the platform supports them
+	#[cfg(any(unix, windows))]
+	pub fn from_file(mut file: File) -> IOResult<Self> {
+		let position = file.stream_position()?;
+		Ok(Self::from_read_at(file)?.offset(position))
+	}
+
+	/// Creates `ReadSlice` from current position of the file to its end
+	#[cfg(not(any(unix, windows)))]
+	pub fn from_file(file: File) -> IOResult<Self> {
+		Self::new(file)
+	}
+
+	/// Returns new slice with `other` reader appended to the end
+	///
+	/// `other` is read from its current position to its end. Fails if it can't be seeked
+	pub fn chain<T: 'a + ReadSeek>(&self, other: T) -> IOResult<Self> {
+		let other: Box<dyn ReadSeek + 'a> = Box::new(other);
+		Ok(self.push(Slice::new(other)?))
+	}
+}
+
+impl<'a> SendReadSlice<'a> {
+	/// Same as `ReadSlice::new`, for sources which can be sent across threads
+	pub fn new_send<T: 'a + ReadSeek + Send>(v: T) -> IOResult<Self> {
+		let v: Box<dyn ReadSeek + Send + 'a> = Box::new(v);
+		Ok(Self::from_slice(Slice::new(v)?))
+	}
+
+	/// Same as `ReadSlice::from_bytes`, for bytes which can be shared across threads
+	pub fn from_bytes_send<T: 'a + AsRef<[u8]> + Send + Sync>(v: T) -> Self {
+		let size = v.as_ref().len() as u64;
+		let v: Arc<dyn ReadAt + Send + Sync + 'a> = Arc::new(Bytes(v));
+		Self::from_slice(Slice::from_read_at(v, size))
+	}
+
+	/// Same as `ReadSlice::from_read_at`, slices can be read from many threads at once
+	pub fn from_read_at_send<T: 'a + ReadAt + Send + Sync>(v: T) -> IOResult<Self> {
+		let size = v.size()?;
+		let v: Arc<dyn ReadAt + Send + Sync + 'a> = Arc::new(v);
+		Ok(Self::from_slice(Slice::from_read_at(v, size)))
+	}
+
+	/// Same as `ReadSlice::chain`, for sources which can be sent across threads
+	pub fn chain_send<T: 'a + ReadSeek + Send>(&self, other: T) -> IOResult<Self> {
+		let other: Box<dyn ReadSeek + Send + 'a> = Box::new(other);
+		Ok(self.push(Slice::new(other)?))
+	}
+}
+
+impl<'a, K: Sharing> ReadSlice<'a, K> {
+	fn from_slice(slice: Slice<'a, K>) -> Self {
 		let size = slice.size;
 		Self {
 			slices: vec![slice],
@@ -138,6 +336,14 @@ impl<'a> ReadSlice<'a> {
 		}
 	}
 
+	fn push(&self, slice: Slice<'a, K>) -> Self {
+		let mut clone = self.clone();
+		clone.size += slice.size;
+		clone.slices.push(slice);
+		clone
+	}
+
+	/// Returns current position relative to the start of the slice
 	pub fn position(&self) -> u64 {
 		let mut pos = 0;
 		for item in &self.slices {
@@ -149,6 +355,9 @@ impl<'a> ReadSlice<'a> {
 		pos
 	}
 
+	/// Returns new slice without first `offset` bytes
+	///
+	/// Offset beyond the end results in empty slice
 	pub fn offset(&self, offset: u64) -> Self {
 		let mut clone = self.clone();
 		let mut c_size = 0;
@@ -168,6 +377,7 @@ impl<'a> ReadSlice<'a> {
 		clone
 	}
 
+	/// Same as `offset`, but modifies the slice in place
 	pub fn offset_mut(&mut self, offset: u64) {
 		let mut c_size = 0;
 		let mut cut = 0;
@@ -185,6 +395,9 @@ impl<'a> ReadSlice<'a> {
 		self.size = self.slices.iter().fold(0, |c, x| c + x.size);
 	}
 
+	/// Returns new slice with first `size` bytes
+	///
+	/// Size beyond the end results in the same slice
 	pub fn take(&self, size: u64) -> Self {
 		let mut clone = self.clone();
 		let mut c_size = size;
@@ -204,6 +417,7 @@ impl<'a> ReadSlice<'a> {
 		clone
 	}
 
+	/// Returns new slice with `size` bytes starting from current position
 	pub fn take_from_current(&self, size: u64) -> Self {
 		let mut clone = self.clone();
 		let pos = clone.position();
@@ -211,27 +425,61 @@ impl<'a> ReadSlice<'a> {
 		ReadSlice::take(&clone, size)
 	}
 
-	pub fn chain<T: 'a + ReadSeek>(&self, other: T) -> Self {
+	/// Returns new slice with `other` slice appended to the end
+	///
+	/// Same as `chain`, but doesn't lock `other` behind another shared reader.
+	/// `other` is read from its current position to its end
+	pub fn append(&self, other: &ReadSlice<'a, K>) -> Self {
+		let other = other.offset(other.position());
 		let mut clone = self.clone();
-		let slice = Slice::new(other);
-		let slice_size = slice.size;
-		clone.slices.push(slice);
-		clone.size += slice_size;
+		clone.size += other.size;
+		clone.slices.extend(other.slices);
 		clone
 	}
 
-	#[allow(dead_code)]
-	pub fn rewind(&mut self) -> &mut Self {
-		self.seek(SeekFrom::Start(0)).unwrap();
-		self
+	/// Creates slice reading `parts` one after another
+	///
+	/// Same as appending parts one by one, but without copying the slice for every part
+	pub fn concat<I: IntoIterator<Item = ReadSlice<'a, K>>>(parts: I) -> Self {
+		let mut out = Self {
+			slices: vec![],
+			size: 0,
+		};
+		for part in parts {
+			let part = part.offset(part.position());
+			out.size += part.size;
+			out.slices.extend(part.slices);
+		}
+		out
+	}
+
+	/// Seeks to the start of the slice
+	pub fn rewind(&mut self) -> IOResult<&mut Self> {
+		self.seek(SeekFrom::Start(0))?;
+		Ok(self)
 	}
 
+	/// Returns size of the slice in bytes
 	pub fn size(&self) -> u64 {
 		self.size
 	}
+
+	/// Returns new slice with `size` bytes starting at `start`
+	pub fn range(&self, start: u64, size: u64) -> Self {
+		ReadSlice::take(&self.offset(start), size)
+	}
+
+	/// Returns new slice of the block with given label
+	///
+	/// `indexes` must describe this slice, returns `None` if label is missing
+	pub fn for_label(&self, indexes: &Indexes, label: &str) -> Option<Self> {
+		indexes
+			.get(label)
+			.map(|(start, size)| self.range(start, size))
+	}
 }
 
-impl<'a> Clone for ReadSlice<'a> {
+impl<'a, K: Sharing> Clone for ReadSlice<'a, K> {
 	fn clone(&self) -> Self {
 		Self {
 			slices: self.slices.clone(),
@@ -240,73 +488,86 @@ impl<'a> Clone for ReadSlice<'a> {
 	}
 }
 
-impl<'a> Read for ReadSlice<'a> {
+impl<'a, K: Sharing> Read for ReadSlice<'a, K> {
 	fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
 		let mut read = 0;
 		let buflen = buffer.len();
 		for slice in &mut self.slices {
 			while !(read >= buflen || slice.position >= slice.size) {
-				read += slice.read(&mut buffer[read..])?;
+				match slice.read(&mut buffer[read..])? {
+					0 => return Ok(read),
+					x => read += x,
+				}
 			}
 		}
 		Ok(read)
 	}
 }
 
-impl<'a> Seek for ReadSlice<'a> {
+impl<'a, K: Sharing> Seek for ReadSlice<'a, K> {
 	fn seek(&mut self, from: SeekFrom) -> IOResult<u64> {
 		match from {
 			SeekFrom::Start(x) => {
-				let mut seek = 0;
+				let mut start = 0;
 				for slice in &mut self.slices {
-					seek += slice.seek(SeekFrom::Start(x - seek))?;
-					if seek >= x {
-						break;
-					}
-				}
-				Ok(seek)
-			}
-			SeekFrom::End(x) => {
-				let p = self.size as i64 - x;
-				self.seek(SeekFrom::Start(p as u64))
-			}
-			SeekFrom::Current(x) => {
-				let mut pos = 0;
-				for slice in &self.slices {
-					pos += slice.position;
-					if slice.position < slice.size {
-						break;
-					}
+					slice.seek(SeekFrom::Start(min(x.saturating_sub(start), slice.size)))?;
+					start += slice.size;
 				}
-				let pos = pos as i64 + x;
-				self.seek(SeekFrom::Start(pos as u64))
+				Ok(x)
 			}
+			SeekFrom::End(x) => match self.size.checked_add_signed(x) {
+				Some(pos) => self.seek(SeekFrom::Start(pos)),
+				None => Err(negative_seek()),
+			},
+			SeekFrom::Current(x) => match self.position().checked_add_signed(x) {
+				Some(pos) => self.seek(SeekFrom::Start(pos)),
+				None => Err(negative_seek()),
+			},
 		}
 	}
 }
 
-impl<'a, T: 'a + Read + Seek + AsRef<[u8]>> From<Cursor<T>> for ReadSlice<'a> {
+impl<'a> From<&'a [u8]> for ReadSlice<'a> {
+	fn from(v: &'a [u8]) -> Self {
+		Self::from_bytes(v)
+	}
+}
+
+impl<'a> From<Vec<u8>> for ReadSlice<'a> {
+	fn from(v: Vec<u8>) -> Self {
+		Self::from_bytes(v)
+	}
+}
+
+/// Slice starts from the current position of the cursor
+impl<'a, T: 'a + AsRef<[u8]>> From<Cursor<T>> for ReadSlice<'a> {
 	fn from(v: Cursor<T>) -> Self {
-		Self::new(v)
+		let position = v.position();
+		Self::from_bytes(v.into_inner()).offset(position)
 	}
 }
 
-impl<'a> From<File> for ReadSlice<'a> {
-	fn from(v: File) -> Self {
+/// Same as `new`, use `from_file` for positional reads
+impl<'a> TryFrom<File> for ReadSlice<'a> {
+	type Error = Error;
+
+	fn try_from(v: File) -> IOResult<Self> {
 		Self::new(v)
 	}
 }
 
 #[cfg(test)]
 mod readslice_tests {
-	use super::ReadSlice;
-	use std::io::{copy, Cursor, Read};
+	use super::{ReadSlice, SendReadSlice};
+	use indexes::Indexes;
+	use readat::ReadAt;
+	use std::io::{copy, Cursor, Error, ErrorKind, Read, Result as IOResult, Seek, SeekFrom};
 
 	#[test]
 	fn works_test() {
 		let vec = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
 		let data = Cursor::new(vec.clone());
-		let mut slice = ReadSlice::new(data);
+		let mut slice = ReadSlice::new(data).unwrap();
 		let mut buf = vec![0; 10];
 		slice.read(&mut buf).unwrap();
 		assert_eq!(buf, vec);
@@ -318,7 +579,7 @@ mod readslice_tests {
 		let vecb = vec![6, 7, 8, 9, 10];
 		let data = Cursor::new(vec.clone());
 		let datab = Cursor::new(vecb.clone());
-		let mut slice = ReadSlice::chain(&ReadSlice::new(data), datab);
+		let mut slice = ReadSlice::chain(&ReadSlice::new(data).unwrap(), datab).unwrap();
 		let mut buf = vec![0; 10];
 		slice.read(&mut buf).unwrap();
 		assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
@@ -330,17 +591,106 @@ mod readslice_tests {
 		let vecb = vec![6, 7, 8, 9, 10];
 		let data = Cursor::new(vec.clone());
 		let datab = Cursor::new(vecb.clone());
-		let mut slice = ReadSlice::chain(&ReadSlice::new(data), ReadSlice::new(datab));
+		let mut slice = ReadSlice::chain(
+			&ReadSlice::new(data).unwrap(),
+			ReadSlice::new(datab).unwrap(),
+		)
+		.unwrap();
 		let mut buf = Cursor::new(vec![]);
 		copy(&mut slice, &mut buf).unwrap();
 		assert_eq!(buf.into_inner(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
 	}
 
+	#[test]
+	fn chain_seek_test() {
+		let slice =
+			ReadSlice::chain(&ReadSlice::from(vec![]), ReadSlice::from(vec![1, 2, 3])).unwrap();
+		let mut slice = ReadSlice::chain(&slice, ReadSlice::from(vec![4, 5])).unwrap();
+		let mut buf = vec![];
+		slice.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, [1, 2, 3, 4, 5]);
+
+		slice.seek(SeekFrom::Start(2)).unwrap();
+		let mut buf = vec![];
+		slice.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, [3, 4, 5]);
+	}
+
+	#[test]
+	fn append_test() {
+		let mut other = ReadSlice::from(vec![3, 4, 5]);
+		other.seek(SeekFrom::Start(1)).unwrap();
+		let mut slice = ReadSlice::from(vec![1, 2]).append(&other);
+		assert_eq!(slice.size(), 4);
+		let mut buf = vec![];
+		slice.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, [1, 2, 4, 5]);
+
+		let parts = vec![ReadSlice::from(vec![1]), other, ReadSlice::from(vec![6])];
+		let mut slice = ReadSlice::concat(parts);
+		assert_eq!(slice.size(), 4);
+		let mut buf = vec![];
+		slice.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, [1, 4, 5, 6]);
+	}
+
+	#[test]
+	fn seek_test() {
+		let slice =
+			ReadSlice::chain(&ReadSlice::from(vec![1, 2, 3]), Cursor::new(vec![4, 5])).unwrap();
+		let mut slice = ReadSlice::offset(&slice, 1);
+		assert_eq!(slice.seek(SeekFrom::End(-1)).unwrap(), 3);
+		let mut buf = vec![];
+		slice.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, [5]);
+
+		assert_eq!(slice.seek(SeekFrom::Current(-3)).unwrap(), 1);
+		assert_eq!(slice.rewind().unwrap().position(), 0);
+		assert!(slice.seek(SeekFrom::End(-5)).is_err());
+		assert!(slice.seek(SeekFrom::Current(-1)).is_err());
+	}
+
+	#[test]
+	fn short_source_test() {
+		struct Short;
+		impl ReadAt for Short {
+			fn read_at(&self, _: &mut [u8], _: u64) -> IOResult<usize> {
+				Ok(0)
+			}
+
+			fn size(&self) -> IOResult<u64> {
+				Ok(4)
+			}
+		}
+		let mut slice = ReadSlice::from_read_at(Short).unwrap();
+		let mut buf = vec![0; 4];
+		assert_eq!(slice.read(&mut buf).unwrap(), 0);
+	}
+
+	#[test]
+	fn seek_error_test() {
+		struct Unseekable;
+		impl Read for Unseekable {
+			fn read(&mut self, _: &mut [u8]) -> IOResult<usize> {
+				Ok(0)
+			}
+		}
+		impl Seek for Unseekable {
+			fn seek(&mut self, _: SeekFrom) -> IOResult<u64> {
+				Err(Error::new(ErrorKind::Unsupported, "Not seekable"))
+			}
+		}
+		let error = ReadSlice::new(Unseekable).unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::Unsupported);
+		let slice = ReadSlice::from(vec![1]);
+		assert!(ReadSlice::chain(&slice, Unseekable).is_err());
+	}
+
 	#[test]
 	fn offset_test() {
 		let vec = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
 		let data = Cursor::new(vec.clone());
-		let mut slice = ReadSlice::offset(&ReadSlice::new(data), 3);
+		let mut slice = ReadSlice::offset(&ReadSlice::new(data).unwrap(), 3);
 		let mut buf = vec![0; 10];
 		slice.read(&mut buf).unwrap();
 		assert_eq!(buf, [4, 5, 6, 7, 8, 9, 10, 0, 0, 0]);
@@ -350,12 +700,27 @@ mod readslice_tests {
 	fn take_test() {
 		let vec = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
 		let data = Cursor::new(vec.clone());
-		let mut slice = ReadSlice::take(&ReadSlice::new(data), 7);
+		let mut slice = ReadSlice::take(&ReadSlice::new(data).unwrap(), 7);
 		let mut buf = vec![0; 10];
 		slice.read(&mut buf).unwrap();
 		assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 0, 0, 0]);
 	}
 
+	#[test]
+	fn from_bytes_test() {
+		let vec = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+		let slice = ReadSlice::from_bytes(vec);
+		let mut taken = ReadSlice::take(&slice.offset(2), 5);
+		let mut buf = vec![];
+		taken.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, [3, 4, 5, 6, 7]);
+
+		taken.seek(SeekFrom::Start(1)).unwrap();
+		let mut buf = vec![0; 2];
+		taken.read_exact(&mut buf).unwrap();
+		assert_eq!(buf, [4, 5]);
+	}
+
 	#[test]
 	fn fn_test() {
 		let vec = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
@@ -363,11 +728,71 @@ mod readslice_tests {
 		let data = Cursor::new(vec.clone());
 		let datab = Cursor::new(vecb.clone());
 		let mut slice = ReadSlice::take(
-			&ReadSlice::chain(&ReadSlice::new(data), datab).offset(11),
+			&ReadSlice::chain(&ReadSlice::new(data).unwrap(), datab)
+				.unwrap()
+				.offset(11),
 			5,
 		);
 		let mut buf = vec![0; 10];
 		slice.read(&mut buf).unwrap();
 		assert_eq!(buf, [12, 13, 14, 15, 16, 0, 0, 0, 0, 0]);
 	}
+
+	#[test]
+	fn from_read_at_test() {
+		let contents = ::std::fs::read("./test_data/a_a.txt").unwrap();
+		let mut file = ::std::fs::File::open("./test_data/a_a.txt").unwrap();
+		file.seek(SeekFrom::Start(2)).unwrap();
+		let slice = ReadSlice::from_file(file).unwrap();
+		let mut a = ReadSlice::take(&slice.offset(2), 4);
+		let mut b = ReadSlice::take(&slice.offset(20), 4);
+		let mut buf_a = vec![0; 2];
+		let mut buf_b = vec![0; 2];
+		for _ in 0..2 {
+			a.read_exact(&mut buf_a).unwrap();
+			b.read_exact(&mut buf_b).unwrap();
+		}
+		assert_eq!(buf_a, &contents[6..8]);
+		assert_eq!(buf_b, &contents[24..26]);
+	}
+
+	#[test]
+	fn for_label_test() {
+		let mut indexes = Indexes::new();
+		indexes.insert("line_0".to_string(), 0, 6);
+		indexes.insert("line_1".to_string(), 6, 6);
+		let data = b"hello\nworld\n";
+		let slice = ReadSlice::from(&data[..]);
+		let mut buf = vec![];
+		slice
+			.for_label(&indexes, "line_1")
+			.unwrap()
+			.read_to_end(&mut buf)
+			.unwrap();
+		assert_eq!(buf, b"world\n");
+		assert!(slice.for_label(&indexes, "line_2").is_none());
+
+		let mut buf = vec![];
+		ReadSlice::from(data.to_vec())
+			.range(2, 3)
+			.read_to_end(&mut buf)
+			.unwrap();
+		assert_eq!(buf, b"llo");
+	}
+
+	#[test]
+	fn send_sync_test() {
+		fn assert_send_sync<T: Send + Sync>(_: &T) {}
+		let slice = SendReadSlice::new_send(Cursor::new(vec![1, 2, 3, 4, 5])).unwrap();
+		assert_send_sync(&slice);
+		let slice = slice.chain_send(Cursor::new(vec![6])).unwrap();
+		assert_send_sync(&SendReadSlice::from_bytes_send(vec![1]).append(&slice));
+		let mut taken = ReadSlice::take(&slice.offset(1), 3);
+		let handle = ::std::thread::spawn(move || {
+			let mut buf = vec![];
+			taken.read_to_end(&mut buf).unwrap();
+			buf
+		});
+		assert_eq!(handle.join().unwrap(), [2, 3, 4]);
+	}
 }
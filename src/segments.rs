@@ -0,0 +1,77 @@
+//! Contains `Segments` iterator
+//!
+//! Describes target of the diff as a sequence of source ranges and literal data,
+//! regardless of how the diff is split into blocks.
+
+use diff_block::DiffBlock;
+use diff_reader::DiffReader;
+use readslice::ReadSlice;
+use std::io::Result as IOResult;
+
+/// Part of the diff target
+#[derive(Debug)]
+pub enum Segment<'a> {
+	/// Copy of `size` source bytes starting at `offset`
+	Source { offset: u64, size: u64 },
+	/// Data stored in the diff
+	Literal(ReadSlice<'a>),
+}
+
+impl<'a> Segment<'a> {
+	/// Returns number of target bytes
+	pub fn size(&self) -> u64 {
+		match self {
+			Segment::Source { size, .. } => *size,
+			Segment::Literal(data) => data.size(),
+		}
+	}
+}
+
+/// Yields target segments of the diff, empty segments are omitted
+pub struct Segments<'a, 'b: 'a> {
+	reader: DiffReader<'a, 'b>,
+	source_position: u64,
+}
+
+impl<'a, 'b: 'a> Segments<'a, 'b> {
+	pub fn new(reader: DiffReader<'a, 'b>) -> Self {
+		Self {
+			reader,
+			source_position: 0,
+		}
+	}
+
+	/// Reads next segment
+	pub fn next_segment(&mut self) -> IOResult<Option<Segment<'b>>> {
+		while let Some(block) = self.reader.next_block()? {
+			let offset = self.source_position;
+			self.source_position += block.source_size();
+			let segment = match block {
+				DiffBlock::Skip { size } => Segment::Source {
+					offset,
+					size: u64::from(size),
+				},
+				DiffBlock::Remove { .. } => continue,
+				DiffBlock::Add { mut data }
+				| DiffBlock::Replace { mut data, .. }
+				| DiffBlock::ReplaceWithSameLength { mut data } => {
+					data.rewind()?;
+					Segment::Literal(data)
+				}
+			};
+			if segment.size() == 0 {
+				continue;
+			}
+			return Ok(Some(segment));
+		}
+		Ok(None)
+	}
+}
+
+impl<'a, 'b: 'a> Iterator for Segments<'a, 'b> {
+	type Item = IOResult<Segment<'b>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.next_segment().transpose()
+	}
+}
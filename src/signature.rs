@@ -0,0 +1,157 @@
+//! Contains `Signature` struct
+
+use functions::{u32_to_u8_be_vec, u64_to_u8_be_vec, vec_to_u32_be, vec_to_u64_be};
+use hasher::{BlockHasher, Digest, Sha256Hasher, MAX_DIGEST_LENGTH};
+use indexes::WithIndexes;
+use lines_with_hash_iterator::{hash_lines_parallel, LinesWithHashIterator};
+use std::io::Result as IOResult;
+
+/// Signature of a file
+///
+/// Signature holds labels, offsets, sizes and hashes of the file blocks, which is everything
+/// diff algorithm needs to know about original file.
+/// It can be computed once, persisted with `to_bytes` and then used
+/// with [create_diff_from_signature](../diff/fn.create_diff_from_signature.html)
+/// to avoid rereading and rehashing of the original file on every diff.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Signature {
+	hasher: String,
+	lines: Vec<(String, u64, u64, Digest)>,
+}
+
+impl Signature {
+	/// Computes signature of the `WithIndexes` implementation with default `Sha256Hasher`
+	pub fn new<T: WithIndexes>(file: T) -> Result<Self, String> {
+		Self::with_hasher(file, Sha256Hasher)
+	}
+
+	/// Computes signature of the `WithIndexes` implementation with given hasher
+	pub fn with_hasher<T: WithIndexes, H: BlockHasher>(file: T, hasher: H) -> Result<Self, String> {
+		let name = hasher.name().to_string();
+		let lines = LinesWithHashIterator::with_hasher(file, hasher)?.collect::<Result<_, _>>()?;
+		Ok(Self {
+			hasher: name,
+			lines,
+		})
+	}
+
+	/// Computes signature hashing blocks on multiple threads
+	///
+	/// See `lines_with_hash_iterator::hash_lines_parallel` for `open` and `threads` meaning
+	pub fn with_hasher_parallel<T, F, H>(open: F, hasher: H, threads: usize) -> Result<Self, String>
+	where
+		T: WithIndexes,
+		F: Fn() -> IOResult<T> + Sync,
+		H: BlockHasher + Sync,
+	{
+		let name = hasher.name().to_string();
+		let lines = hash_lines_parallel(open, hasher, threads)?;
+		Ok(Self {
+			hasher: name,
+			lines,
+		})
+	}
+
+	/// Returns name of the hasher signature was computed with
+	pub fn hasher(&self) -> &str {
+		&self.hasher
+	}
+
+	/// Returns signature lines as `(label, start, size, hash)`
+	pub fn lines(&self) -> &[(String, u64, u64, Digest)] {
+		&self.lines
+	}
+
+	/// Returns binary representation of `Signature`
+	///
+	/// Format is BigEndian:
+	/// ```bash
+	/// hasher_length : 4
+	/// hasher : hasher_length // utf-8
+	/// hash_length : 4
+	/// lines_count : 4
+	/// line_{n} : {...}
+	///   label_length : 4
+	///   label : label_length // utf-8
+	///   start : 8
+	///   size : 8
+	///   hash : hash_length
+	/// ```
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let hash_length = self.lines.first().map_or(0, |x| x.3.as_bytes().len());
+		let mut out = vec![];
+		out.extend_from_slice(&u32_to_u8_be_vec(self.hasher.len() as u32));
+		out.extend_from_slice(self.hasher.as_bytes());
+		out.extend_from_slice(&u32_to_u8_be_vec(hash_length as u32));
+		out.extend_from_slice(&u32_to_u8_be_vec(self.lines.len() as u32));
+		for (label, start, size, hash) in &self.lines {
+			out.extend_from_slice(&u32_to_u8_be_vec(label.len() as u32));
+			out.extend_from_slice(label.as_bytes());
+			out.extend_from_slice(&u64_to_u8_be_vec(*start));
+			out.extend_from_slice(&u64_to_u8_be_vec(*size));
+			out.extend_from_slice(hash.as_bytes());
+		}
+		out
+	}
+
+	/// Parses `Signature` from binary representation produced by `to_bytes`
+	pub fn from_bytes(input: &[u8]) -> Result<Self, String> {
+		let mut lines = vec![];
+		let mut pos = 0;
+		let mut take = |len: usize| -> Result<&[u8], String> {
+			if input.len() - pos < len {
+				return Err("Unexpected end of signature data".to_string());
+			}
+			pos += len;
+			Ok(&input[pos - len..pos])
+		};
+		let hasher_length = vec_to_u32_be(take(4)?) as usize;
+		let hasher = String::from_utf8(take(hasher_length)?.to_vec())
+			.map_err(|_| "Hasher name is not a valid utf-8 string".to_string())?;
+		let hash_length = vec_to_u32_be(take(4)?) as usize;
+		if hash_length > MAX_DIGEST_LENGTH {
+			return Err("Hash length is too big".to_string());
+		}
+		let count = vec_to_u32_be(take(4)?);
+		for _ in 0..count {
+			let label_length = vec_to_u32_be(take(4)?) as usize;
+			let label = String::from_utf8(take(label_length)?.to_vec())
+				.map_err(|_| "Label is not a valid utf-8 string".to_string())?;
+			let start = vec_to_u64_be(take(8)?);
+			let size = vec_to_u64_be(take(8)?);
+			let hash = Digest::new(take(hash_length)?);
+			lines.push((label, start, size, hash));
+		}
+		if pos != input.len() {
+			return Err("Unexpected data after signature".to_string());
+		}
+		Ok(Self { hasher, lines })
+	}
+}
+
+#[cfg(test)]
+mod signature_tests {
+	use super::Signature;
+	use hasher::XxHash64Hasher;
+	use test_mod::TextFile;
+
+	#[test]
+	fn bytes_round_trip_test() {
+		let file = TextFile::from_path("./test_data/a_a.txt");
+		let signature = Signature::new(file).unwrap();
+		assert_eq!(signature.lines().len(), 6);
+		assert_eq!(signature.hasher(), "sha256");
+
+		let bytes = signature.to_bytes();
+		assert_eq!(Signature::from_bytes(&bytes).unwrap(), signature);
+		assert!(Signature::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+		let file = TextFile::from_path("./test_data/a_a.txt");
+		let signature = Signature::with_hasher(file, XxHash64Hasher).unwrap();
+		assert_eq!(signature.hasher(), "xxh64");
+		assert_eq!(
+			Signature::from_bytes(&signature.to_bytes()).unwrap(),
+			signature
+		);
+	}
+}
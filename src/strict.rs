@@ -0,0 +1,268 @@
+//! Contains strict diff application
+//!
+//! `apply_diff` doesn't report sources shorter than the diff expects and fails on truncated
+//! diffs without telling where. `apply_diff_strict` reports both as `ApplyError` wrapped into
+//! `std::io::Error`, which can be inspected with `ApplyError::from_io`.
+
+use decoder::Decoder;
+use diff_block::DiffBlockN;
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{copy, sink, BufWriter, Error, ErrorKind, Read, Result as IOResult, Write};
+
+/// Reason of the strict application failure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyErrorKind {
+	/// Diff ends in the middle of the block header
+	TruncatedHeader,
+	/// Diff ends before the block data, contains number of missing bytes
+	TruncatedPayload(u64),
+	/// Source ends before the block, contains number of missing bytes
+	SourceUnderrun(u64),
+	/// Source has bytes after the last block, contains their number
+	UnconsumedSource(u64),
+	/// Block action is unknown
+	UnknownAction(u16),
+}
+
+/// Error of the strict application with the location of the failing block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyError {
+	/// Reason of the failure
+	pub kind: ApplyErrorKind,
+	/// Index of the failing block
+	pub block: u64,
+	/// Offset of the failing block in the diff
+	pub diff_offset: u64,
+	/// Source offset at the start of the failing block
+	pub source_offset: u64,
+	/// Target offset at the start of the failing block
+	pub target_offset: u64,
+}
+
+impl ApplyError {
+	/// Returns `ApplyError` contained in the `io::Error`, if any
+	pub fn from_io(error: &Error) -> Option<&Self> {
+		error.get_ref().and_then(|x| x.downcast_ref::<Self>())
+	}
+}
+
+impl Display for ApplyError {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		match self.kind {
+			ApplyErrorKind::TruncatedHeader => write!(f, "Diff is truncated in block header")?,
+			ApplyErrorKind::TruncatedPayload(x) => write!(
+				f,
+				"Diff is truncated, {} bytes of block data are missing",
+				x
+			)?,
+			ApplyErrorKind::SourceUnderrun(x) => {
+				write!(f, "Source is {} bytes shorter than the diff expects", x)?
+			}
+			ApplyErrorKind::UnconsumedSource(x) => {
+				write!(f, "Source has {} bytes after the last block", x)?
+			}
+			ApplyErrorKind::UnknownAction(x) => write!(f, "Unknown action {}", x)?,
+		};
+		write!(
+			f,
+			" (block {}, diff offset {}, source offset {}, target offset {})",
+			self.block, self.diff_offset, self.source_offset, self.target_offset
+		)
+	}
+}
+
+impl StdError for ApplyError {}
+
+impl From<ApplyError> for Error {
+	fn from(error: ApplyError) -> Self {
+		let kind = match error.kind {
+			ApplyErrorKind::TruncatedHeader
+			| ApplyErrorKind::TruncatedPayload(_)
+			| ApplyErrorKind::SourceUnderrun(_) => ErrorKind::UnexpectedEof,
+			_ => ErrorKind::InvalidData,
+		};
+		Error::new(kind, error)
+	}
+}
+
+fn fail(error: &ApplyError, kind: ApplyErrorKind) -> Error {
+	Error::from(ApplyError {
+		kind,
+		..error.clone()
+	})
+}
+
+/// Applies diff failing on truncated diff and on source shorter than the diff expects
+///
+/// If `require_consumed` is set, also fails if source has bytes after the last block.
+/// Failures are reported as `ApplyError` wrapped into `io::Error`. Returns size of the target
+pub fn apply_diff_strict<T: Read, U: Read, W: Write>(
+	file: &mut T,
+	diff: &mut U,
+	output: &mut W,
+	require_consumed: bool,
+) -> IOResult<u64> {
+	let mut output = BufWriter::with_capacity(1024 * 64, output);
+	let mut error = ApplyError {
+		kind: ApplyErrorKind::TruncatedHeader,
+		block: 0,
+		diff_offset: 0,
+		source_offset: 0,
+		target_offset: 0,
+	};
+	let mut decoder = Decoder::new();
+	let mut buf = [0u8; 1024 * 8];
+	loop {
+		error.diff_offset = decoder.offset();
+		let block = match decoder.read_block(diff) {
+			Ok(Some(x)) => x,
+			Ok(None) if decoder.is_complete() => break,
+			Ok(None) => return Err(fail(&error, ApplyErrorKind::TruncatedHeader)),
+			Err(e) => {
+				return Err(match decoder.action() {
+					Some(x) if x > 4 => fail(&error, ApplyErrorKind::UnknownAction(x)),
+					_ => e,
+				})
+			}
+		};
+		let (from_source, remove) = match block {
+			DiffBlockN::Skip(size) => (u64::from(size), 0),
+			DiffBlockN::Add(_) => (0, 0),
+			DiffBlockN::Remove(size)
+			| DiffBlockN::Replace(size, _)
+			| DiffBlockN::ReplaceWithSameLength(size) => (0, u64::from(size)),
+		};
+		let copied = copy(&mut file.take(from_source), &mut output)?;
+		if copied < from_source {
+			return Err(fail(
+				&error,
+				ApplyErrorKind::SourceUnderrun(from_source - copied),
+			));
+		}
+		let removed = copy(&mut file.take(remove), &mut sink())?;
+		if removed < remove {
+			return Err(fail(
+				&error,
+				ApplyErrorKind::SourceUnderrun(remove - removed),
+			));
+		}
+		let mut added = 0;
+		while let Some(data) = decoder.read_data(diff, &mut buf)? {
+			output.write_all(data)?;
+			added += data.len() as u64;
+		}
+		if !decoder.is_complete() {
+			return Err(fail(
+				&error,
+				ApplyErrorKind::TruncatedPayload(decoder.needed()),
+			));
+		}
+		error.block += 1;
+		error.source_offset += from_source + remove;
+		error.target_offset += from_source + added;
+	}
+	if require_consumed {
+		let left = copy(file, &mut sink())?;
+		if left > 0 {
+			return Err(fail(&error, ApplyErrorKind::UnconsumedSource(left)));
+		}
+	}
+	output.flush()?;
+	Ok(error.target_offset)
+}
+
+#[cfg(test)]
+mod strict_tests {
+	use super::{apply_diff_strict, ApplyError, ApplyErrorKind};
+	use diff::create_diff;
+	use std::fs::read;
+	use std::io::Cursor;
+	use test_mod::TextFile;
+
+	fn fail_kind(
+		source: &[u8],
+		diff: &[u8],
+		require_consumed: bool,
+	) -> Option<(ApplyErrorKind, u64)> {
+		let mut out = vec![];
+		let result = apply_diff_strict(
+			&mut Cursor::new(source),
+			&mut Cursor::new(diff),
+			&mut out,
+			require_consumed,
+		);
+		result.err().map(|e| {
+			ApplyError::from_io(&e)
+				.map(|x| (x.kind.clone(), x.block))
+				.unwrap()
+		})
+	}
+
+	#[test]
+	fn works_test() {
+		let mut diff = vec![];
+		create_diff(
+			&mut TextFile::from_path("./test_data/a_a.txt"),
+			&mut TextFile::from_path("./test_data/a_b.txt"),
+			&mut diff,
+		)
+		.unwrap();
+		let source = read("./test_data/a_a.txt").unwrap();
+		let expected = read("./test_data/a_b.txt").unwrap();
+		let mut out = vec![];
+		let size = apply_diff_strict(
+			&mut Cursor::new(&source),
+			&mut Cursor::new(&diff),
+			&mut out,
+			false,
+		)
+		.unwrap();
+		assert_eq!(out, expected);
+		assert_eq!(size, expected.len() as u64);
+	}
+
+	#[test]
+	fn fail_test() {
+		let source = b"0123456789";
+		let skip = [0, 0, 0, 0, 0, 4];
+		let add = [0, 1, 0, 0, 0, 3, b'a', b'b', b'c'];
+		let diff: Vec<u8> = skip.iter().chain(add.iter()).cloned().collect();
+
+		assert_eq!(fail_kind(source, &diff, false), None);
+		assert_eq!(
+			fail_kind(source, &diff, true),
+			Some((ApplyErrorKind::UnconsumedSource(6), 2))
+		);
+		assert_eq!(
+			fail_kind(source, &diff[..diff.len() - 1], false),
+			Some((ApplyErrorKind::TruncatedPayload(1), 1))
+		);
+		assert_eq!(
+			fail_kind(source, &diff[..8], false),
+			Some((ApplyErrorKind::TruncatedHeader, 1))
+		);
+		assert_eq!(
+			fail_kind(&source[..2], &diff, false),
+			Some((ApplyErrorKind::SourceUnderrun(2), 0))
+		);
+		assert_eq!(
+			fail_kind(source, &[0, 7, 0, 0, 0, 1], false),
+			Some((ApplyErrorKind::UnknownAction(7), 0))
+		);
+
+		let error = apply_diff_strict(
+			&mut Cursor::new(&source[..]),
+			&mut Cursor::new(&diff[..diff.len() - 2]),
+			&mut vec![],
+			false,
+		)
+		.unwrap_err();
+		let error = ApplyError::from_io(&error).unwrap();
+		assert_eq!(error.kind, ApplyErrorKind::TruncatedPayload(2));
+		assert_eq!(
+			(error.diff_offset, error.source_offset, error.target_offset),
+			(6, 4, 4)
+		);
+	}
+}